@@ -1,3 +1,4 @@
+use crate::assets::asset_url;
 use crate::error_template::{AppError, ErrorTemplate};
 use leptos::prelude::*;
 use leptos_meta::*;
@@ -12,13 +13,12 @@ pub fn App() -> impl IntoView {
     let error_fallback = || {
         let mut outside_errors = Errors::default();
         outside_errors.insert_with_default_key(AppError::NotFound);
-        view! { <ErrorTemplate outside_errors/> }
-        .into_view()
+        view! { <ErrorTemplate outside_errors/> }.into_view()
     };
 
     view! {
         <Title text="Welcome to Cache Response Example"/>
-        <Stylesheet id="leptos" href="/pkg/cache-response.css"/>
+        <Stylesheet id="leptos" href=asset_url("/pkg/cache-response.css")/>
 
         // content for this welcome page
         <Router>
@@ -31,24 +31,52 @@ pub fn App() -> impl IntoView {
     }
 }
 
+/// The cache policy of the routes above, consulted by the cache middleware.
+#[cfg(feature = "ssr")]
+pub fn route_policies() -> crate::cache::RoutePolicies {
+    use crate::cache::{CachePolicy, RoutePolicies};
+    use std::time::Duration;
+
+    // browsers revalidate the page, the server renders it once a minute
+    RoutePolicies::default().route(
+        "/",
+        CachePolicy::public(Duration::ZERO).s_maxage(Duration::from_secs(60)),
+    )
+}
+
 /// Renders the home page of your application.
 #[component]
 fn HomePage() -> impl IntoView {
     // Creates a reactive value to update the button
     let count = RwSignal::new(0);
     let on_click = move |_| count.update(|count| *count += 1);
+    let refresh = ServerAction::<RefreshHomePage>::new();
 
     view! {
         <h1>"Cache response !"</h1>
         <p>"Reload the page and open your browser console to check the code status for js, css and wasm files !"</p>
-        <p>"Expected to equal 206 and Cached-Control to equal 1 year."</p>
+        <p>"Expected to equal 200, with a Cache-Control of 1 year for the hashed css and 5 minutes for js and wasm."</p>
         <button on:click=on_click>"Click Me: " {count}</button>
+        <p>"This page is cached for a minute, unless you have a session cookie."</p>
+        <ActionForm action=refresh>
+            <button type="submit">"Render it again"</button>
+        </ActionForm>
     }
 }
 
+/// Drops the cached renders of the home page, the next visit renders it again.
+#[server]
+pub async fn refresh_home_page() -> Result<usize, ServerFnError> {
+    use crate::cache::ResponseCache;
+
+    let cache = use_context::<ResponseCache>()
+        .ok_or_else(|| ServerFnError::new("The response cache is not provided"))?;
+    Ok(cache.invalidate("/").await)
+}
+
 pub fn shell(options: LeptosOptions) -> impl IntoView {
     view! {
-        <!DOCTYPE html> 
+        <!DOCTYPE html>
         <html lang="en">
             <head>
                 <meta charset="utf-8"/>
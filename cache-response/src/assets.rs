@@ -0,0 +1,122 @@
+use leptos::prelude::use_context;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Maps the logical paths of the site assets to their content-hashed URLs.
+///
+/// `/pkg/cache-response.css` is served as `/pkg/cache-response.<hash>.css`, the hash
+/// changing with the content of the file, so a new build gets new URLs and the hashed
+/// responses can be cached forever.
+#[derive(Clone, Debug, Default)]
+pub struct AssetManifest {
+    hashed: Arc<HashMap<String, String>>,
+    logical: Arc<HashMap<String, String>>,
+}
+
+impl AssetManifest {
+    /// A manifest from `(logical path, hashed URL)` pairs.
+    pub fn new(assets: impl IntoIterator<Item = (String, String)>) -> Self {
+        let hashed: HashMap<String, String> = assets.into_iter().collect();
+        let logical = hashed
+            .iter()
+            .map(|(logical, hashed)| (hashed.clone(), logical.clone()))
+            .collect();
+        Self {
+            hashed: Arc::new(hashed),
+            logical: Arc::new(logical),
+        }
+    }
+
+    /// The hashed URL of a logical path.
+    pub fn hashed(&self, path: &str) -> Option<&str> {
+        self.hashed.get(path).map(String::as_str)
+    }
+
+    /// The logical path a hashed URL stands for.
+    pub fn logical(&self, path: &str) -> Option<&str> {
+        self.logical.get(path).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashed.is_empty()
+    }
+}
+
+/// The URL to reference an asset with, hashed when an [`AssetManifest`] is provided
+/// as context :
+///
+/// ```ignore
+/// <Stylesheet id="leptos" href=asset_url("/pkg/cache-response.css")/>
+/// ```
+///
+/// Without a manifest, in the browser, the logical path is returned. The hydrated
+/// elements keep the URL rendered by the server.
+pub fn asset_url(path: &str) -> String {
+    use_context::<AssetManifest>()
+        .and_then(|manifest| manifest.hashed(path).map(str::to_owned))
+        .unwrap_or_else(|| path.to_owned())
+}
+
+#[cfg(feature = "ssr")]
+mod fingerprint {
+    use super::AssetManifest;
+    use crate::cache::{warm, Encoding};
+    use sha2::{Digest, Sha256};
+    use std::io;
+    use std::path::Path;
+
+    impl AssetManifest {
+        /// Fingerprints every file of `root/dir`, the URLs being relative to `root`.
+        ///
+        /// The pre-compressed siblings (`app.wasm.br`, ...) are served through their
+        /// original file and left out.
+        pub async fn from_dir(root: impl AsRef<Path>, dir: impl AsRef<Path>) -> io::Result<Self> {
+            let root = root.as_ref();
+            let mut assets = Vec::new();
+            let mut dirs = vec![root.join(dir)];
+            while let Some(dir) = dirs.pop() {
+                let mut entries = tokio::fs::read_dir(&dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    let file_type = entry.file_type().await?;
+                    if file_type.is_dir() {
+                        dirs.push(path);
+                    } else if file_type.is_file() && !is_precompressed(&path) {
+                        let Some(logical) = warm::site_path(root, &path) else {
+                            continue;
+                        };
+                        let body = tokio::fs::read(&path).await?;
+                        let hashed = hashed_path(&logical, &body);
+                        assets.push((logical, hashed));
+                    }
+                }
+            }
+            Ok(Self::new(assets))
+        }
+    }
+
+    fn is_precompressed(path: &Path) -> bool {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        Encoding::COMPRESSED
+            .iter()
+            .any(|encoding| extension == encoding.extension())
+    }
+
+    /// Inserts the first 64 bits of the body SHA-256 before the extension,
+    /// `/pkg/app.css` becoming `/pkg/app.0123456789abcdef.css`.
+    fn hashed_path(path: &str, body: &[u8]) -> String {
+        let digest = Sha256::digest(body);
+        let hash: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        match name.split_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                format!("{dir}/{stem}.{hash}.{extension}")
+            }
+            _ => format!("{dir}/{name}.{hash}"),
+        }
+    }
+}
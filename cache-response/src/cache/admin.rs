@@ -0,0 +1,308 @@
+use super::layer::{content_type, respond};
+use super::{CacheConfig, ResponseCache};
+use axum::body::{to_bytes, Body};
+use axum::extract::Query;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tower::{Service, ServiceExt};
+
+/// Default path prefix of the admin endpoints.
+pub const DEFAULT_ADMIN_PREFIX: &str = "/_cache";
+
+/// Default header carrying the admin shared secret.
+pub const DEFAULT_ADMIN_HEADER: &str = "x-cache-admin-secret";
+
+/// Largest body accepted by the warm-up endpoint.
+const MAX_WARM_BODY: usize = 64 * 1024;
+
+/// Configuration of the cache admin endpoints, disabled unless set with
+/// [`CacheConfig::admin`].
+///
+/// | Method   | Path                      | Action                                  |
+/// |----------|---------------------------|-----------------------------------------|
+/// | `GET`    | `{prefix}/stats`          | [`CacheStats`](super::CacheStats) as JSON |
+/// | `GET`    | `{prefix}/keys`           | the stored entries as JSON              |
+/// | `DELETE` | `{prefix}/keys?uri=/a`    | purges every variant of `/a`            |
+/// | `DELETE` | `{prefix}/keys?prefix=/a` | purges every URI starting with `/a`     |
+/// | `POST`   | `{prefix}/warm`           | fetches a JSON list of URIs into the cache |
+///
+/// Every request must carry the shared secret in the admin header, a request without it
+/// gets a *401 Unauthorized* and one with another secret a *403 Forbidden*. So do the
+/// scrapes of the [`metrics`](CacheConfig::metrics).
+///
+/// `Router::layer` wraps each route on its own, for the warm-up to reach every route
+/// the [`ResponseCacheLayer`](super::ResponseCacheLayer) must wrap the whole router :
+///
+/// ```ignore
+/// let app = ResponseCacheLayer::with_config(cache, config).layer(router);
+/// axum::serve(listener, ServiceExt::<Request>::into_make_service(app)).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct AdminConfig {
+    prefix: String,
+    header: HeaderName,
+    secret: HeaderValue,
+}
+
+impl AdminConfig {
+    /// Admin endpoints under [`DEFAULT_ADMIN_PREFIX`] accepting `secret`
+    /// in the [`DEFAULT_ADMIN_HEADER`] header.
+    pub fn new(secret: HeaderValue) -> Self {
+        Self {
+            prefix: DEFAULT_ADMIN_PREFIX.to_owned(),
+            header: HeaderName::from_static(DEFAULT_ADMIN_HEADER),
+            secret,
+        }
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The admin route of `path`, like `/stats`, if it is under the prefix.
+    pub fn route<'a>(&self, path: &'a str) -> Option<&'a str> {
+        path.strip_prefix(self.prefix.as_str())
+            .filter(|route| route.is_empty() || route.starts_with('/'))
+    }
+
+    /// The response to a request without the secret, `None` when `headers` carry it.
+    pub(super) fn reject(&self, headers: &HeaderMap) -> Option<Response<Body>> {
+        let reject = |status| Some((status, "Invalid cache admin secret").into_response());
+        let Some(secret) = headers.get(&self.header) else {
+            return reject(StatusCode::UNAUTHORIZED);
+        };
+        let (given, expected) = (secret.as_bytes(), self.secret.as_bytes());
+        // compare every byte so the time taken doesn't leak the secret
+        let matches = !expected.is_empty()
+            && given.len() == expected.len()
+            && given
+                .iter()
+                .zip(expected)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        match matches {
+            true => None,
+            false => reject(StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Purge {
+    uri: Option<String>,
+    prefix: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Purged {
+    purged: usize,
+}
+
+#[derive(Serialize)]
+struct Warmed {
+    uri: String,
+    status: Option<u16>,
+    cache: Option<&'static str>,
+}
+
+/// Answers a request for the admin `route`.
+pub(super) async fn handle<T>(
+    inner: T,
+    cache: &ResponseCache,
+    config: &Arc<CacheConfig>,
+    admin: &AdminConfig,
+    route: &str,
+    req: Request<Body>,
+) -> Response<Body>
+where
+    T: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    if let Some(rejection) = admin.reject(req.headers()) {
+        return rejection;
+    }
+
+    match (req.method().clone(), route) {
+        (Method::GET, "/stats") => Json(cache.stats().await).into_response(),
+        (Method::GET, "/keys") => Json(cache.entries().await).into_response(),
+        (Method::DELETE, "/keys") => {
+            let purged = match Query::<Purge>::try_from_uri(req.uri()) {
+                Ok(Query(Purge { uri: Some(uri), .. })) => cache.purge_uri(&uri).await,
+                Ok(Query(Purge {
+                    prefix: Some(prefix),
+                    ..
+                })) => cache.purge_prefix(&prefix).await,
+                _ => {
+                    let message = "Expected a `uri` or a `prefix` query parameter";
+                    return (StatusCode::BAD_REQUEST, message).into_response();
+                }
+            };
+            log::info!("purged {purged} entries from the cache");
+            Json(Purged { purged }).into_response()
+        }
+        (Method::POST, "/warm") => {
+            let uris = match to_bytes(req.into_body(), MAX_WARM_BODY).await {
+                Ok(body) => serde_json::from_slice::<Vec<String>>(&body),
+                Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+            };
+            match uris {
+                Ok(uris) => Json(warm(inner, cache, config, uris).await).into_response(),
+                Err(error) => (StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+            }
+        }
+        (_, "/stats" | "/keys" | "/warm") => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Fetches every URI through the cache, so the cacheable ones get stored.
+async fn warm<T>(
+    inner: T,
+    cache: &ResponseCache,
+    config: &Arc<CacheConfig>,
+    uris: Vec<String>,
+) -> Vec<Warmed>
+where
+    T: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    let mut warmed = Vec::with_capacity(uris.len());
+    for uri in uris {
+        let mut result = Warmed {
+            uri,
+            status: None,
+            cache: None,
+        };
+        let Ok(parsed) = result.uri.parse::<Uri>() else {
+            warmed.push(result);
+            continue;
+        };
+        let mut req = Request::new(Body::empty());
+        *req.uri_mut() = parsed;
+
+        let mut inner = inner.clone();
+        if inner.ready().await.is_err() {
+            warmed.push(result);
+            continue;
+        }
+        let served = respond(inner, cache, config, req).await.ok();
+        if let Some((response, status)) = served {
+            cache.record(status, content_type(&response));
+            result.status = Some(response.status().as_u16());
+            result.cache = Some(status.as_str());
+            // streamed bodies are only stored once they went through
+            _ = to_bytes(response.into_body(), usize::MAX).await;
+        }
+        warmed.push(result);
+    }
+    warmed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::testing::{get, Origin};
+    use crate::cache::{ResponseCacheLayer, ResponseCacheService};
+    use tower::Layer;
+
+    const SECRET: &str = "s3cret";
+
+    fn service(origin: &Origin) -> ResponseCacheService<Origin> {
+        let config = CacheConfig::new()
+            .content_type("text/css", HeaderValue::from_static("public, max-age=300"))
+            .admin(AdminConfig::new(HeaderValue::from_static(SECRET)));
+        ResponseCacheLayer::with_config(ResponseCache::builder().build(), config)
+            .layer(origin.clone())
+    }
+
+    async fn admin(
+        service: &ResponseCacheService<Origin>,
+        method: Method,
+        uri: &str,
+        secret: Option<&'static str>,
+        body: &'static str,
+    ) -> (StatusCode, String) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(secret) = secret {
+            request = request.header(DEFAULT_ADMIN_HEADER, secret);
+        }
+        let request = request.body(Body::from(body)).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn checks_the_secret() {
+        let service = service(&Origin::new());
+        let stats = "/_cache/stats";
+
+        let (status, _) = admin(&service, Method::GET, stats, None, "").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        for wrong in ["s3creT", "s3cret!", ""] {
+            let (status, _) = admin(&service, Method::GET, stats, Some(wrong), "").await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{wrong:?}");
+        }
+        let (status, body) = admin(&service, Method::GET, stats, Some(SECRET), "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#""entries":0"#), "{body}");
+    }
+
+    #[tokio::test]
+    async fn purges_a_uri_or_a_prefix() {
+        let origin = Origin::new();
+        let service = service(&origin);
+        for uri in ["/a/one.css", "/a/two.css", "/a/two.css?v=2", "/b.css"] {
+            assert_eq!(get(&service, uri).await.unwrap().0, "MISS");
+        }
+        let delete = |uri| admin(&service, Method::DELETE, uri, Some(SECRET), "");
+
+        let purged = delete("/_cache/keys?uri=/a/two.css").await;
+        assert_eq!(purged, (StatusCode::OK, r#"{"purged":1}"#.to_owned()));
+        assert_eq!(get(&service, "/a/two.css?v=2").await.unwrap().0, "HIT");
+
+        let purged = delete("/_cache/keys?prefix=/a/").await;
+        assert_eq!(purged, (StatusCode::OK, r#"{"purged":2}"#.to_owned()));
+        assert_eq!(get(&service, "/a/one.css").await.unwrap().0, "MISS");
+        assert_eq!(get(&service, "/b.css").await.unwrap().0, "HIT");
+
+        let (status, _) = delete("/_cache/keys").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn warms_the_uris() {
+        let origin = Origin::new();
+        let service = service(&origin);
+
+        let uris = r#"["/app.css", "not a uri"]"#;
+        let (status, body) =
+            admin(&service, Method::POST, "/_cache/warm", Some(SECRET), uris).await;
+        assert_eq!(status, StatusCode::OK);
+        let warmed: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            warmed,
+            serde_json::json!([
+                { "uri": "/app.css", "status": 200, "cache": "MISS" },
+                { "uri": "not a uri", "status": null, "cache": null },
+            ])
+        );
+        assert_eq!(get(&service, "/app.css").await.unwrap().0, "HIT");
+        assert_eq!(origin.calls(), 1);
+
+        let (status, _) = admin(&service, Method::POST, "/_cache/warm", Some(SECRET), "{}").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = admin(&service, Method::GET, "/_cache/warm", Some(SECRET), "").await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
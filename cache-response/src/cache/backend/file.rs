@@ -0,0 +1,560 @@
+use super::{CacheStore, MemoryStore, StoreFuture, StoreLimits, StoreStats};
+use crate::cache::{CacheKey, CachedResponse, Encoding, EntryInfo, Expiry, Lookup};
+use axum::body::Bytes;
+use axum::http::{response::Parts, HeaderName, HeaderValue, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{watch, Mutex};
+
+/// Name of the index listing the stored entries.
+const INDEX: &str = "index.json";
+
+/// How long the index waits for more changes before being written.
+const SYNC_DELAY: Duration = Duration::from_millis(500);
+
+/// Keeps the entries in memory and persists them in a directory, one file per body
+/// plus an index of the keys and headers, so a restarted server comes up warm.
+///
+/// ```ignore
+/// let cache = ResponseCache::builder()
+///     .max_bytes(64 * 1024 * 1024)
+///     .open("target/cache")
+///     .await?;
+/// ```
+///
+/// The entries are read from the directory once, when the store is opened. Only the
+/// identity responses are loaded back, a compressed variant could be the one of a
+/// previous build, and is compressed again from the current response when requested.
+/// A failure to write to the directory is logged and the entry is still served from
+/// memory.
+///
+/// A request only writes the body file of its entry. The index is rewritten in the
+/// background, at most twice a second, by a task which also removes the files of the
+/// evicted entries and writes the index a last time when the store is dropped.
+#[derive(Debug)]
+pub struct FileStore {
+    directory: Arc<Directory>,
+    /// Wakes the task writing the index up.
+    changed: watch::Sender<()>,
+}
+
+#[derive(Debug)]
+struct Directory {
+    memory: MemoryStore,
+    dir: PathBuf,
+    /// The body file of each persisted entry, locked while the directory is updated.
+    files: Mutex<HashMap<CacheKey, String>>,
+}
+
+/// A stored entry, as written in the index.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    uri: String,
+    encoding: String,
+    vary: Vec<(String, Option<String>)>,
+    status: u16,
+    headers: Vec<(String, String)>,
+    file: String,
+    /// Milliseconds since the Unix epoch.
+    stored_at: u64,
+    expires_at: Option<u64>,
+    stale_until: Option<u64>,
+}
+
+impl FileStore {
+    /// Opens the store persisted in `dir`, creating the directory if needed.
+    ///
+    /// The compressed variants, the entries past their grace period and the files not
+    /// listed in the index are removed.
+    pub async fn open(dir: impl Into<PathBuf>, limits: StoreLimits) -> io::Result<Self> {
+        let dir = dir.into();
+        tokio::fs::create_dir_all(&dir).await?;
+        let index: Vec<IndexEntry> = match tokio::fs::read(dir.join(INDEX)).await {
+            Ok(index) => serde_json::from_slice(&index).unwrap_or_else(|error| {
+                log::warn!(
+                    "ignoring the invalid cache index in {}: {error}",
+                    dir.display()
+                );
+                Vec::new()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+
+        let store = Directory {
+            memory: MemoryStore::new(limits),
+            dir,
+            files: Mutex::default(),
+        };
+        let now = Now::new();
+        let mut files = store.files.lock().await;
+        for entry in index {
+            if entry.stale_until.is_some_and(|until| until <= now.millis) {
+                continue;
+            }
+            let Some((key, parts)) = entry.decode() else {
+                continue;
+            };
+            if key.encoding() != Encoding::Identity {
+                continue;
+            }
+            let Ok(body) = tokio::fs::read(store.dir.join(&entry.file)).await else {
+                continue;
+            };
+            let response = CachedResponse {
+                parts,
+                body: Bytes::from(body),
+                stored_at: now.instant(entry.stored_at),
+                expires_at: entry.expires_at.map(|at| now.instant(at)),
+            };
+            let stale_until = entry.stale_until.map(|at| now.instant(at));
+            if store
+                .memory
+                .restore(key.clone(), response, stale_until)
+                .is_some()
+            {
+                files.insert(key, entry.file);
+            }
+        }
+        store.sync(&mut files).await?;
+        // left by an interrupted write
+        let mut entries = tokio::fs::read_dir(&store.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(name) = name.to_str().filter(|name| name.ends_with(".body")) else {
+                continue;
+            };
+            if !files.values().any(|file| file == name) {
+                remove_file(&entry.path()).await?;
+            }
+        }
+        drop(files);
+
+        let stats = store.memory.stats();
+        log::info!(
+            "loaded {} cache entries ({} bytes) from {}",
+            stats.entries,
+            stats.bytes,
+            store.dir.display()
+        );
+
+        let directory = Arc::new(store);
+        let (changed, mut changes) = watch::channel(());
+        tokio::spawn({
+            let directory = directory.clone();
+            async move {
+                // ends once the store is dropped
+                while changes.changed().await.is_ok() {
+                    tokio::time::sleep(SYNC_DELAY).await;
+                    changes.mark_unchanged();
+                    directory.persist().await;
+                }
+                directory.persist().await;
+            }
+        });
+        Ok(Self { directory, changed })
+    }
+
+    async fn insert(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> Option<CachedResponse> {
+        let directory = &self.directory;
+        let mut files = directory.files.lock().await;
+        let Some(stored) = directory.memory.insert(key.clone(), parts, body, expiry) else {
+            // the previous response left the memory, it must not be loaded back either
+            if let Some(previous) = files.remove(&key) {
+                _ = remove_file(&directory.dir.join(previous)).await;
+                self.changed.send_replace(());
+            }
+            return None;
+        };
+        // named after the content too, so the index never points to another response
+        let file = body_file(&key, &stored.body);
+        match tokio::fs::write(directory.dir.join(&file), &stored.body).await {
+            Ok(()) => {
+                if let Some(previous) = files.insert(key.clone(), file.clone()) {
+                    if previous != file {
+                        _ = remove_file(&directory.dir.join(previous)).await;
+                    }
+                }
+                self.changed.send_replace(());
+            }
+            Err(error) => {
+                log::error!(
+                    "failed to persist {key} in {}: {error}",
+                    directory.dir.display()
+                );
+            }
+        }
+        Some(stored)
+    }
+
+    /// Applies a change to the entries in memory, the directory being updated later.
+    async fn update<T>(&self, change: impl FnOnce(&MemoryStore) -> T) -> T {
+        let changed = change(&self.directory.memory);
+        self.changed.send_replace(());
+        changed
+    }
+
+    /// Writes the index of the entries in memory and removes the files of the others
+    /// right away, rather than in the background.
+    pub async fn flush(&self) -> io::Result<()> {
+        let mut files = self.directory.files.lock().await;
+        self.directory.sync(&mut files).await
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.directory.dir
+    }
+
+    fn memory(&self) -> &MemoryStore {
+        &self.directory.memory
+    }
+}
+
+impl Directory {
+    async fn persist(&self) {
+        let mut files = self.files.lock().await;
+        if let Err(error) = self.sync(&mut files).await {
+            log::error!(
+                "failed to update the cache in {}: {error}",
+                self.dir.display()
+            );
+        }
+    }
+
+    /// Writes the index of the entries in memory and removes the files of the others.
+    async fn sync(&self, files: &mut HashMap<CacheKey, String>) -> io::Result<()> {
+        let now = Now::new();
+        let snapshot = self.memory.snapshot();
+        let mut kept = HashMap::with_capacity(snapshot.len());
+        let mut index = Vec::with_capacity(snapshot.len());
+        for (key, response, stale_until) in snapshot {
+            let Some(file) = files.remove(&key) else {
+                continue;
+            };
+            if let Some(entry) = IndexEntry::encode(&key, &response, stale_until, &file, &now) {
+                index.push(entry);
+                kept.insert(key, file);
+            } else {
+                files.insert(key, file);
+            }
+        }
+        // evicted, expired, purged or not representable in the index
+        for file in files.values() {
+            remove_file(&self.dir.join(file)).await?;
+        }
+        *files = kept;
+
+        let index = serde_json::to_vec(&index).map_err(io::Error::other)?;
+        let tmp = self.dir.join(format!("{INDEX}.tmp"));
+        tokio::fs::write(&tmp, index).await?;
+        tokio::fs::rename(&tmp, self.dir.join(INDEX)).await
+    }
+}
+
+impl CacheStore for FileStore {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Lookup> {
+        // an entry dropped on lookup leaves its file until the next update
+        Box::pin(std::future::ready(self.memory().lookup(key)))
+    }
+
+    fn put(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> StoreFuture<'_, Option<CachedResponse>> {
+        Box::pin(self.insert(key, parts, body, expiry))
+    }
+
+    fn delete<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Option<CachedResponse>> {
+        Box::pin(self.update(move |memory| memory.remove(key)))
+    }
+
+    fn purge_uri<'a>(&'a self, uri: &'a str) -> StoreFuture<'a, usize> {
+        Box::pin(self.update(move |memory| memory.purge(|key| key.uri() == uri)))
+    }
+
+    fn purge_prefix<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, usize> {
+        Box::pin(self.update(move |memory| memory.purge(|key| key.uri().starts_with(prefix))))
+    }
+
+    fn clear(&self) -> StoreFuture<'_, ()> {
+        Box::pin(self.update(MemoryStore::clear))
+    }
+
+    fn vary<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Vec<HeaderName>> {
+        Box::pin(std::future::ready(self.memory().vary(key)))
+    }
+
+    fn stats(&self) -> StoreFuture<'_, StoreStats> {
+        Box::pin(std::future::ready(self.memory().stats()))
+    }
+
+    fn entries(&self) -> StoreFuture<'_, Vec<EntryInfo>> {
+        Box::pin(std::future::ready(self.memory().entries()))
+    }
+
+    fn limits(&self) -> &StoreLimits {
+        self.memory().limits()
+    }
+}
+
+impl IndexEntry {
+    /// `None` when a header value isn't text, the entry then stays in memory only.
+    fn encode(
+        key: &CacheKey,
+        response: &CachedResponse,
+        stale_until: Option<Instant>,
+        file: &str,
+        now: &Now,
+    ) -> Option<Self> {
+        let text = |value: &HeaderValue| value.to_str().ok().map(str::to_owned);
+        let vary = key
+            .vary()
+            .iter()
+            .map(|(name, value)| match value {
+                Some(value) => Some((name.to_string(), Some(text(value)?))),
+                None => Some((name.to_string(), None)),
+            })
+            .collect::<Option<_>>()?;
+        let headers = response
+            .parts
+            .headers
+            .iter()
+            .map(|(name, value)| Some((name.to_string(), text(value)?)))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            uri: key.uri().to_owned(),
+            encoding: key.encoding().as_str().to_owned(),
+            vary,
+            status: response.parts.status.as_u16(),
+            headers,
+            file: file.to_owned(),
+            stored_at: now.millis_at(response.stored_at),
+            expires_at: response.expires_at.map(|at| now.millis_at(at)),
+            stale_until: stale_until.map(|at| now.millis_at(at)),
+        })
+    }
+
+    fn decode(&self) -> Option<(CacheKey, Parts)> {
+        let encoding = Encoding::from_token(&self.encoding)?;
+        let vary = self
+            .vary
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::try_from(name).ok()?;
+                match value {
+                    Some(value) => Some((name, Some(HeaderValue::try_from(value).ok()?))),
+                    None => Some((name, None)),
+                }
+            })
+            .collect::<Option<_>>()?;
+        let key = CacheKey::new(&axum::http::Method::GET, &self.uri.parse().ok()?)?
+            .with_encoding(encoding)
+            .with_vary_values(vary);
+
+        let (mut parts, ()) = Response::new(()).into_parts();
+        parts.status = StatusCode::from_u16(self.status).ok()?;
+        for (name, value) in &self.headers {
+            let name = HeaderName::try_from(name).ok()?;
+            parts
+                .headers
+                .append(name, HeaderValue::try_from(value).ok()?);
+        }
+        Some((key, parts))
+    }
+}
+
+/// The current time, to convert the instants of the entries to and from timestamps.
+struct Now {
+    instant: Instant,
+    millis: u64,
+}
+
+impl Now {
+    fn new() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            instant: Instant::now(),
+            millis: since_epoch.as_millis() as u64,
+        }
+    }
+
+    fn millis_at(&self, instant: Instant) -> u64 {
+        if instant >= self.instant {
+            self.millis + (instant - self.instant).as_millis() as u64
+        } else {
+            let elapsed = (self.instant - instant).as_millis() as u64;
+            self.millis.saturating_sub(elapsed)
+        }
+    }
+
+    fn instant(&self, millis: u64) -> Instant {
+        if millis >= self.millis {
+            self.instant + Duration::from_millis(millis - self.millis)
+        } else {
+            let elapsed = Duration::from_millis(self.millis - millis);
+            self.instant.checked_sub(elapsed).unwrap_or(self.instant)
+        }
+    }
+}
+
+/// The name of the file holding `body` for `key`.
+fn body_file(key: &CacheKey, body: &Bytes) -> String {
+    let digest = Sha256::new()
+        .chain_update(key.to_string())
+        .chain_update(body)
+        .finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!("{hex}.body")
+}
+
+async fn remove_file(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A temporary directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static DIRS: AtomicUsize = AtomicUsize::new(0);
+            Self(std::env::temp_dir().join(format!(
+                "cache-response-store-{}-{}",
+                std::process::id(),
+                DIRS.fetch_add(1, Ordering::Relaxed)
+            )))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn limits() -> StoreLimits {
+        StoreLimits {
+            max_entry_bytes: Some(1024),
+            ..StoreLimits::default()
+        }
+    }
+
+    fn key(uri: &'static str) -> CacheKey {
+        CacheKey::new(&axum::http::Method::GET, &uri.parse().unwrap()).unwrap()
+    }
+
+    async fn put(store: &FileStore, uri: &'static str, body: Vec<u8>) -> bool {
+        put_key(store, key(uri), body).await
+    }
+
+    async fn put_key(store: &FileStore, key: CacheKey, body: Vec<u8>) -> bool {
+        let (parts, ()) = Response::new(()).into_parts();
+        let body = Bytes::from(body);
+        store
+            .put(key, parts, body, Expiry::default())
+            .await
+            .is_some()
+    }
+
+    fn index(dir: &Path) -> Vec<IndexEntry> {
+        serde_json::from_slice(&std::fs::read(dir.join(INDEX)).unwrap()).unwrap()
+    }
+
+    fn body_files(dir: &Path) -> usize {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(".body")
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn comes_back_warm() {
+        let dir = TempDir::new();
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        for uri in ["/app.css", "/app.js"] {
+            assert!(put(&store, uri, b"body".to_vec()).await);
+        }
+        // only the bodies are written on the request path
+        assert_eq!(index(&dir.0).len(), 0);
+        store.flush().await.unwrap();
+        drop(store);
+
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(matches!(store.get(&key("/app.css")).await, Lookup::Hit(_)));
+        assert_eq!(store.stats().await.entries, 2);
+        assert_eq!(body_files(&dir.0), 2);
+    }
+
+    #[tokio::test]
+    async fn leaves_the_compressed_variants_behind() {
+        let dir = TempDir::new();
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(put(&store, "/app.css", b"body".to_vec()).await);
+        let brotli = key("/app.css").with_encoding(Encoding::Brotli);
+        assert!(put_key(&store, brotli.clone(), b"compressed".to_vec()).await);
+        store.flush().await.unwrap();
+        assert_eq!(index(&dir.0).len(), 2);
+        drop(store);
+
+        // the next build may have changed the file
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(matches!(store.get(&brotli).await, Lookup::Miss));
+        assert!(matches!(store.get(&key("/app.css")).await, Lookup::Hit(_)));
+        assert_eq!(index(&dir.0).len(), 1);
+        assert_eq!(body_files(&dir.0), 1);
+    }
+
+    #[tokio::test]
+    async fn forgets_the_entries_replaced_by_a_rejected_one() {
+        let dir = TempDir::new();
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(put(&store, "/app.css", b"body".to_vec()).await);
+        store.flush().await.unwrap();
+
+        assert!(!put(&store, "/app.css", vec![b'x'; 2048]).await);
+        assert_eq!(body_files(&dir.0), 0);
+        store.flush().await.unwrap();
+        drop(store);
+
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(matches!(store.get(&key("/app.css")).await, Lookup::Miss));
+    }
+
+    #[tokio::test]
+    async fn writes_the_index_in_the_background() {
+        let dir = TempDir::new();
+        let store = FileStore::open(&dir.0, limits()).await.unwrap();
+        assert!(put(&store, "/app.css", b"body".to_vec()).await);
+        tokio::time::sleep(SYNC_DELAY * 2).await;
+
+        let index = index(&dir.0);
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[0].uri, "/app.css");
+    }
+}
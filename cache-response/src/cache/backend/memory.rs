@@ -0,0 +1,512 @@
+use super::{CacheStore, StoreFuture, StoreLimits, StoreStats};
+use crate::cache::key::{self, CacheKey};
+use crate::cache::store::weight;
+use crate::cache::{CachedResponse, Encoding, EntryInfo, EvictionPolicy, Expiry, Lookup};
+use axum::body::Bytes;
+use axum::http::{response::Parts, HeaderName};
+use std::collections::{BTreeMap, HashMap};
+use std::future::ready;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// Keeps the entries in memory, lost when the process exits.
+#[derive(Debug)]
+pub struct MemoryStore {
+    limits: StoreLimits,
+    inner: Mutex<Store>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(StoreLimits::default())
+    }
+}
+
+impl MemoryStore {
+    pub fn new(limits: StoreLimits) -> Self {
+        Self {
+            inner: Mutex::new(Store {
+                limits: limits.clone(),
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                vary: HashMap::new(),
+                tick: 0,
+                bytes: 0,
+                evictions: 0,
+            }),
+            limits,
+        }
+    }
+
+    pub(super) fn lookup(&self, key: &CacheKey) -> Lookup {
+        self.lock().lookup(key)
+    }
+
+    pub(super) fn insert(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> Option<CachedResponse> {
+        let stored_at = Instant::now();
+        let expires_at = expiry.ttl.or(self.limits.ttl).map(|ttl| stored_at + ttl);
+        let response = CachedResponse {
+            parts,
+            body,
+            stored_at,
+            expires_at,
+        };
+        let stale_until = expires_at.map(|expires_at| expires_at + expiry.grace);
+        let mut store = self.lock();
+        // the variants were compressed from the response being replaced
+        if key.encoding() == Encoding::Identity {
+            store.remove_variants(&key);
+        }
+        store.insert(key, response, stale_until)
+    }
+
+    /// Stores an entry as it was, like one read back from the disk.
+    pub(super) fn restore(
+        &self,
+        key: CacheKey,
+        response: CachedResponse,
+        stale_until: Option<Instant>,
+    ) -> Option<CachedResponse> {
+        self.lock().insert(key, response, stale_until)
+    }
+
+    pub(super) fn remove(&self, key: &CacheKey) -> Option<CachedResponse> {
+        self.lock().remove(key)
+    }
+
+    pub(super) fn purge(&self, matches: impl Fn(&CacheKey) -> bool) -> usize {
+        self.lock().purge(matches)
+    }
+
+    pub(super) fn clear(&self) {
+        let mut store = self.lock();
+        store.entries.clear();
+        store.order.clear();
+        store.vary.clear();
+        store.bytes = 0;
+    }
+
+    pub(super) fn vary(&self, key: &CacheKey) -> Vec<HeaderName> {
+        self.lock()
+            .vary
+            .get(&key.base())
+            .map(|variants| variants.names.clone())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn stats(&self) -> StoreStats {
+        let store = self.lock();
+        StoreStats {
+            entries: store.entries.len(),
+            bytes: store.bytes,
+            evictions: store.evictions,
+        }
+    }
+
+    pub(super) fn entries(&self) -> Vec<EntryInfo> {
+        let store = self.lock();
+        store
+            .order
+            .values()
+            .filter_map(|key| {
+                let slot = store.entries.get(key)?;
+                Some(EntryInfo {
+                    key: key.to_string(),
+                    uri: key.uri().to_owned(),
+                    encoding: key.encoding().as_str(),
+                    bytes: slot.weight,
+                    age_secs: slot.response.stored_at.elapsed().as_secs(),
+                    ttl_secs: slot.response.ttl_left().map(|ttl| ttl.as_secs()),
+                })
+            })
+            .collect()
+    }
+
+    /// The stored entries with the end of their grace period, the next to be evicted first.
+    pub(super) fn snapshot(&self) -> Vec<(CacheKey, CachedResponse, Option<Instant>)> {
+        let store = self.lock();
+        store
+            .order
+            .values()
+            .filter_map(|key| {
+                let slot = store.entries.get(key)?;
+                Some((key.clone(), slot.response.clone(), slot.stale_until))
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Store> {
+        // a panic while holding the lock can't leave the store half updated
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Lookup> {
+        Box::pin(ready(self.lookup(key)))
+    }
+
+    fn put(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> StoreFuture<'_, Option<CachedResponse>> {
+        Box::pin(ready(self.insert(key, parts, body, expiry)))
+    }
+
+    fn delete<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Option<CachedResponse>> {
+        Box::pin(ready(self.remove(key)))
+    }
+
+    fn purge_uri<'a>(&'a self, uri: &'a str) -> StoreFuture<'a, usize> {
+        Box::pin(ready(self.purge(|key| key.uri() == uri)))
+    }
+
+    fn purge_prefix<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, usize> {
+        Box::pin(ready(self.purge(|key| key.uri().starts_with(prefix))))
+    }
+
+    fn clear(&self) -> StoreFuture<'_, ()> {
+        MemoryStore::clear(self);
+        Box::pin(ready(()))
+    }
+
+    fn vary<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Vec<HeaderName>> {
+        Box::pin(ready(MemoryStore::vary(self, key)))
+    }
+
+    fn stats(&self) -> StoreFuture<'_, StoreStats> {
+        Box::pin(ready(MemoryStore::stats(self)))
+    }
+
+    fn entries(&self) -> StoreFuture<'_, Vec<EntryInfo>> {
+        Box::pin(ready(MemoryStore::entries(self)))
+    }
+
+    fn limits(&self) -> &StoreLimits {
+        &self.limits
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    response: CachedResponse,
+    tick: u64,
+    weight: usize,
+    /// The entry is dropped instead of served stale past this instant.
+    stale_until: Option<Instant>,
+}
+
+/// The `Vary` header names of a resource, kept while one of its variants is stored.
+#[derive(Debug, Default)]
+struct Variants {
+    names: Vec<HeaderName>,
+    /// Number of stored entries sharing the base key.
+    count: usize,
+    weight: usize,
+}
+
+#[derive(Debug)]
+struct Store {
+    limits: StoreLimits,
+    entries: HashMap<CacheKey, Slot>,
+    /// Entries ordered by `Slot::tick`, the first one is evicted first.
+    order: BTreeMap<u64, CacheKey>,
+    /// `Vary` header names of each resource, by [`CacheKey::base`].
+    vary: HashMap<CacheKey, Variants>,
+    tick: u64,
+    bytes: usize,
+    evictions: u64,
+}
+
+impl Store {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn lookup(&mut self, key: &CacheKey) -> Lookup {
+        let Some(slot) = self.entries.get(key) else {
+            return Lookup::Miss;
+        };
+        if !slot.response.is_fresh() {
+            if slot.stale_until.is_some_and(|until| Instant::now() < until) {
+                return Lookup::Stale(slot.response.clone());
+            }
+            self.remove(key);
+            return Lookup::Expired;
+        }
+
+        if self.limits.eviction == EvictionPolicy::Lru {
+            let tick = self.next_tick();
+            if let Some(slot) = self.entries.get_mut(key) {
+                self.order.remove(&slot.tick);
+                self.order.insert(tick, key.clone());
+                slot.tick = tick;
+            }
+        }
+
+        match self.entries.get(key) {
+            Some(slot) => Lookup::Hit(slot.response.clone()),
+            None => Lookup::Miss,
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: CacheKey,
+        response: CachedResponse,
+        stale_until: Option<Instant>,
+    ) -> Option<CachedResponse> {
+        let weight = weight(&response.parts, &response.body);
+        let names = match key.encoding() {
+            Encoding::Identity => key::vary_names(&response.parts.headers).unwrap_or_default(),
+            _ => self
+                .vary
+                .get(&key.base())
+                .map(|variants| variants.names.clone())
+                .unwrap_or_default(),
+        };
+        let vary_weight = vary_weight(&key, &names);
+        let too_large = self
+            .limits
+            .entry_limit()
+            .is_some_and(|max| weight + vary_weight > max);
+        // an outdated entry must not outlive the response replacing it
+        self.remove(&key);
+        if self.limits.max_entries == 0 || too_large {
+            return None;
+        }
+        let variants = self.vary.entry(key.base()).or_default();
+        variants.count += 1;
+        variants.names = names;
+        self.bytes = self.bytes + vary_weight - variants.weight;
+        variants.weight = vary_weight;
+
+        let tick = self.next_tick();
+        self.bytes += weight;
+        self.order.insert(tick, key.clone());
+        let slot = Slot {
+            response: response.clone(),
+            tick,
+            weight,
+            stale_until,
+        };
+        self.entries.insert(key.clone(), slot);
+
+        self.evict();
+        // the budget may not leave room for the entry itself
+        self.entries.contains_key(&key).then_some(response)
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> Option<CachedResponse> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&slot.tick);
+        self.bytes -= slot.weight;
+        self.release(key);
+        Some(slot.response)
+    }
+
+    /// Removes the encoded variants of an identity entry.
+    fn remove_variants(&mut self, identity: &CacheKey) {
+        if self
+            .vary
+            .get(&identity.base())
+            .is_none_or(|variants| variants.count < 2)
+        {
+            return;
+        }
+        let variants: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .filter(|key| {
+                key.encoding() != Encoding::Identity
+                    && key.with_encoding(Encoding::Identity) == *identity
+            })
+            .cloned()
+            .collect();
+        for key in &variants {
+            self.remove(key);
+        }
+    }
+
+    /// Forgets the `Vary` names of a resource once its last variant is gone.
+    fn release(&mut self, key: &CacheKey) {
+        let base = key.base();
+        let Some(variants) = self.vary.get_mut(&base) else {
+            return;
+        };
+        variants.count -= 1;
+        if variants.count == 0 {
+            self.bytes -= variants.weight;
+            self.vary.remove(&base);
+        }
+    }
+
+    fn purge(&mut self, matches: impl Fn(&CacheKey) -> bool) -> usize {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .filter(|key| matches(key))
+            .cloned()
+            .collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        keys.len()
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.limits.max_entries
+            || self.limits.max_bytes.is_some_and(|max| self.bytes > max)
+        {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(slot) = self.entries.remove(&key) {
+                log::debug!("evicting {key} ({} bytes)", slot.weight);
+                self.bytes -= slot.weight;
+                self.evictions += 1;
+                self.release(&key);
+            }
+        }
+    }
+}
+
+/// Number of bytes the `Vary` names of a resource account for in the cache budget.
+fn vary_weight(key: &CacheKey, names: &[HeaderName]) -> usize {
+    let names: usize = names.iter().map(|name| name.as_str().len()).sum();
+    std::mem::size_of::<Variants>() + key.uri().len() + names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderMap, HeaderValue, Method, Response, Uri};
+    use std::time::Duration;
+
+    fn key(uri: &'static str, language: &'static str) -> CacheKey {
+        let mut request = HeaderMap::new();
+        request.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_static(language));
+        CacheKey::new(&Method::GET, &Uri::from_static(uri))
+            .unwrap()
+            .with_vary(&[header::ACCEPT_LANGUAGE], &request)
+    }
+
+    fn put(store: &MemoryStore, key: CacheKey, ttl: Option<Duration>) {
+        let (parts, ()) = Response::builder()
+            .header(header::VARY, "accept-language")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let expiry = Expiry {
+            ttl,
+            grace: Duration::ZERO,
+        };
+        store.insert(key, parts, Bytes::from_static(b"body"), expiry);
+    }
+
+    fn vary_len(store: &MemoryStore) -> usize {
+        store.lock().vary.len()
+    }
+
+    #[test]
+    fn forgets_the_vary_names_with_the_last_variant() {
+        let store = MemoryStore::default();
+        put(&store, key("/page", "en"), None);
+        put(&store, key("/page", "fr"), None);
+        let bytes = store.stats().bytes;
+        assert_eq!(store.vary(&key("/page", "de")), [header::ACCEPT_LANGUAGE]);
+
+        store.remove(&key("/page", "en"));
+        assert_eq!(vary_len(&store), 1);
+        store.remove(&key("/page", "fr"));
+        assert_eq!(vary_len(&store), 0);
+        assert_eq!(store.stats().bytes, 0);
+        assert!(bytes > 0);
+
+        put(&store, key("/expired", "en"), Some(Duration::ZERO));
+        assert!(matches!(
+            store.lookup(&key("/expired", "en")),
+            Lookup::Expired
+        ));
+        assert_eq!(vary_len(&store), 0);
+    }
+
+    #[test]
+    fn rejects_the_entries_over_the_budget_with_their_vary_names() {
+        let (parts, ()) = Response::builder()
+            .header(header::VARY, "accept-language")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let body = Bytes::from_static(b"body");
+        let key = key("/page", "en");
+        let entry = weight(&parts, &body);
+        let store = MemoryStore::new(StoreLimits {
+            max_bytes: Some(entry),
+            ..StoreLimits::default()
+        });
+
+        let expiry = Expiry {
+            ttl: None,
+            grace: Duration::ZERO,
+        };
+        assert!(store.insert(key.clone(), parts, body, expiry).is_none());
+        assert!(matches!(store.lookup(&key), Lookup::Miss));
+        assert_eq!(store.stats().bytes, 0);
+        assert_eq!(vary_len(&store), 0);
+    }
+
+    #[test]
+    fn drops_the_variants_of_a_replaced_response() {
+        let store = MemoryStore::default();
+        let en = key("/page", "en");
+        put(&store, en.clone(), None);
+        put(&store, en.with_encoding(Encoding::Brotli), None);
+        put(
+            &store,
+            key("/page", "fr").with_encoding(Encoding::Brotli),
+            None,
+        );
+
+        put(&store, en.clone(), None);
+        assert!(matches!(
+            store.lookup(&en.with_encoding(Encoding::Brotli)),
+            Lookup::Miss
+        ));
+        // another variant of the resource is left alone
+        let fr = key("/page", "fr").with_encoding(Encoding::Brotli);
+        assert!(matches!(store.lookup(&fr), Lookup::Hit(_)));
+        assert_eq!(store.stats().entries, 2);
+    }
+
+    #[test]
+    fn counts_the_vary_names_in_the_budget() {
+        let store = MemoryStore::new(StoreLimits {
+            max_entries: 2,
+            ..StoreLimits::default()
+        });
+        // arbitrary query strings, each with its own base key
+        for query in 0..100 {
+            let uri = format!("/page?{query}");
+            let key = CacheKey::new(&Method::GET, &uri.parse().unwrap()).unwrap();
+            put(&store, key, None);
+        }
+        assert_eq!(store.stats().evictions, 98);
+        assert_eq!(vary_len(&store), 2);
+
+        let entries: usize = store.entries().iter().map(|entry| entry.bytes).sum();
+        assert!(store.stats().bytes > entries);
+        store.clear();
+        assert_eq!(store.stats().bytes, 0);
+    }
+}
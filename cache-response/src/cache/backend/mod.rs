@@ -0,0 +1,102 @@
+//! Storage backends of the [`ResponseCache`](super::ResponseCache).
+//!
+//! [`MemoryStore`] keeps the entries in the process, [`FileStore`] also persists them
+//! in a directory so a restarted server comes up warm. Other backends implement
+//! [`CacheStore`] and are plugged with [`ResponseCache::with_store`](super::ResponseCache::with_store).
+
+mod file;
+mod memory;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+
+use super::{CacheKey, CachedResponse, EntryInfo, EvictionPolicy, Expiry, Lookup};
+use axum::body::Bytes;
+use axum::http::{response::Parts, HeaderName};
+use std::{fmt, future::Future, pin::Pin, time::Duration};
+
+/// Default number of entries kept by a [`CacheStore`].
+pub const DEFAULT_MAX_ENTRIES: usize = 200;
+
+/// The future returned by the [`CacheStore`] methods.
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Where a [`ResponseCache`](super::ResponseCache) keeps its entries.
+///
+/// A store enforces its [`StoreLimits`], evicting entries as needed, and drops the
+/// entries past their grace period when they are looked up.
+pub trait CacheStore: fmt::Debug + Send + Sync + 'static {
+    /// Looks `key` up, telling a fresh entry from a stale or expired one.
+    fn get<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Lookup>;
+
+    /// Stores a response, returns `None` when it is too large to be stored.
+    fn put(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> StoreFuture<'_, Option<CachedResponse>>;
+
+    fn delete<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Option<CachedResponse>>;
+
+    /// Drops every entry, all variants included, of the resource at `uri`.
+    fn purge_uri<'a>(&'a self, uri: &'a str) -> StoreFuture<'a, usize>;
+
+    /// Drops every entry whose URI starts with `prefix`.
+    fn purge_prefix<'a>(&'a self, prefix: &'a str) -> StoreFuture<'a, usize>;
+
+    fn clear(&self) -> StoreFuture<'_, ()>;
+
+    /// The request headers the last stored response for `key.base()` varies on.
+    fn vary<'a>(&'a self, key: &'a CacheKey) -> StoreFuture<'a, Vec<HeaderName>>;
+
+    fn stats(&self) -> StoreFuture<'_, StoreStats>;
+
+    /// Describes the stored entries, the next to be evicted first.
+    fn entries(&self) -> StoreFuture<'_, Vec<EntryInfo>>;
+
+    fn limits(&self) -> &StoreLimits;
+}
+
+/// Size limits and expiration shared by the [`CacheStore`] implementations,
+/// see [`ResponseCacheBuilder`](super::ResponseCacheBuilder).
+#[derive(Clone, Debug)]
+pub struct StoreLimits {
+    pub max_entries: usize,
+    pub max_bytes: Option<usize>,
+    pub max_entry_bytes: Option<usize>,
+    pub ttl: Option<Duration>,
+    pub eviction: EvictionPolicy,
+}
+
+impl Default for StoreLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: None,
+            max_entry_bytes: None,
+            ttl: None,
+            eviction: EvictionPolicy::default(),
+        }
+    }
+}
+
+impl StoreLimits {
+    /// The largest weight of a single entry, an entry larger than the whole budget
+    /// would evict everything else and then itself.
+    pub fn entry_limit(&self) -> Option<usize> {
+        match (self.max_entry_bytes, self.max_bytes) {
+            (Some(entry), Some(total)) => Some(entry.min(total)),
+            (entry, total) => entry.or(total),
+        }
+    }
+}
+
+/// The size of a [`CacheStore`], the request counters being kept by the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub evictions: u64,
+}
@@ -0,0 +1,172 @@
+use axum::body::{Body, Bytes, HttpBody};
+use http_body::{Frame, SizeHint};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// Called with the whole body once it has been streamed to the client.
+pub type Commit = Box<dyn FnOnce(Bytes) + Send>;
+
+/// A body streaming the inner body to the client while keeping a copy of it.
+///
+/// The copy is handed to the [`Commit`] callback as soon as the inner body is complete:
+/// once it holds the `length` announced by the response, its `Content-Length` or the
+/// exact size hint of the inner body, or once the inner body reports its end. hyper
+/// stops polling a body whose `Content-Length` has been sent, so waiting for the final
+/// `None` would never commit on a real connection.
+/// Past `limit` bytes, on a read error, if the body ends short of its `length` or if
+/// the client goes away, the copy is dropped and nothing gets committed, the response
+/// itself is left untouched.
+pub struct TeeBody {
+    inner: Body,
+    buffer: Option<Vec<u8>>,
+    limit: usize,
+    /// The length announced by the response, if any.
+    length: Option<usize>,
+    commit: Option<Commit>,
+}
+
+impl TeeBody {
+    pub fn new(inner: Body, limit: usize, length: Option<usize>, commit: Commit) -> Self {
+        let length = length.or_else(|| usize::try_from(inner.size_hint().exact()?).ok());
+        let capacity = length.map_or(0, |length| length.min(limit));
+        Self {
+            inner,
+            buffer: Some(Vec::with_capacity(capacity)),
+            limit,
+            length,
+            commit: Some(commit),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        let Some(buffer) = &self.buffer else {
+            return false;
+        };
+        self.length == Some(buffer.len()) || self.inner.is_end_stream()
+    }
+
+    fn finish(&mut self) {
+        let Some(buffer) = self.buffer.take() else {
+            return;
+        };
+        // a truncated body would be replayed with the full `Content-Length`
+        if self.length.is_some_and(|length| length != buffer.len()) {
+            log::debug!(
+                "the body ended after {} bytes, it won't be cached",
+                buffer.len()
+            );
+            self.abort();
+            return;
+        }
+        if let Some(commit) = self.commit.take() {
+            commit(Bytes::from(buffer));
+        }
+    }
+
+    fn abort(&mut self) {
+        self.buffer = None;
+        self.commit = None;
+    }
+}
+
+impl HttpBody for TeeBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                if let (Some(data), Some(buffer)) = (frame.data_ref(), this.buffer.as_mut()) {
+                    if buffer.len() + data.len() > this.limit {
+                        log::debug!("the body exceeds {} bytes, it won't be cached", this.limit);
+                        this.abort();
+                    } else {
+                        buffer.extend_from_slice(data);
+                    }
+                }
+                if frame.is_data() && this.is_complete() {
+                    this.finish();
+                }
+                Poll::Ready(Some(Ok(frame)))
+            }
+            Some(Err(error)) => {
+                this.abort();
+                Poll::Ready(Some(Err(error)))
+            }
+            None => {
+                this.finish();
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    /// A body sending its chunks, then ending.
+    struct Chunks(VecDeque<Bytes>);
+
+    impl HttpBody for Chunks {
+        type Data = Bytes;
+        type Error = axum::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            Poll::Ready(self.0.pop_front().map(|chunk| Ok(Frame::data(chunk))))
+        }
+    }
+
+    /// Streams `chunks` announced as `length` bytes, returns what got committed.
+    async fn tee(chunks: &[&'static str], length: Option<usize>) -> Option<Bytes> {
+        let committed = Arc::new(Mutex::new(None));
+        let commit: Commit = Box::new({
+            let committed = committed.clone();
+            move |bytes| *committed.lock().unwrap() = Some(bytes)
+        });
+        let chunks = chunks
+            .iter()
+            .map(|chunk| Bytes::from_static(chunk.as_bytes()));
+        let inner = Body::new(Chunks(chunks.collect()));
+        let body = Body::new(TeeBody::new(inner, 1024, length, commit));
+        _ = to_bytes(body, usize::MAX).await;
+        let committed = committed.lock().unwrap().take();
+        committed
+    }
+
+    #[tokio::test]
+    async fn commits_the_whole_body() {
+        assert_eq!(
+            tee(&["hello ", "world"], Some(11)).await.unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            tee(&["hello ", "world"], None).await.unwrap(),
+            "hello world"
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_a_body_ending_short() {
+        assert_eq!(tee(&["hello ", "wor"], Some(11)).await, None);
+        assert_eq!(tee(&[], Some(11)).await, None);
+    }
+}
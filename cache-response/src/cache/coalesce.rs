@@ -0,0 +1,181 @@
+use super::CacheKey;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Resources remembered as not cacheable, past this number they are all forgotten.
+const MAX_PASSES: usize = 1024;
+
+/// The misses being populated, so concurrent requests for the same key wait for the
+/// first one instead of all reaching the inner service.
+///
+/// Resources whose last response wasn't cacheable, like server rendered pages, are
+/// remembered so their requests don't wait on each other for nothing.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight {
+    misses: Arc<Mutex<HashMap<CacheKey, watch::Receiver<()>>>>,
+    passes: Arc<Mutex<HashSet<CacheKey>>>,
+}
+
+/// The part a request plays in populating a missing entry.
+pub enum Flight {
+    /// The first request for the key, it populates the entry while holding the guard.
+    Leader(FlightGuard),
+    /// Another request is populating the entry.
+    Follower(watch::Receiver<()>),
+    /// The resource wasn't cacheable last time, the request goes on its own.
+    Pass,
+}
+
+impl InFlight {
+    pub fn join(&self, key: &CacheKey) -> Flight {
+        let passes = self.passes.lock().unwrap_or_else(PoisonError::into_inner);
+        if passes.contains(&key.base()) {
+            return Flight::Pass;
+        }
+        drop(passes);
+
+        let mut misses = self.misses.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(receiver) = misses.get(key) {
+            return Flight::Follower(receiver.clone());
+        }
+        let (sender, receiver) = watch::channel(());
+        misses.insert(key.clone(), receiver);
+        Flight::Leader(FlightGuard {
+            key: key.clone(),
+            misses: self.misses.clone(),
+            _sender: sender,
+        })
+    }
+
+    /// Remembers that the response for `key` can't be cached.
+    pub fn pass(&self, key: &CacheKey) {
+        let mut passes = self.passes.lock().unwrap_or_else(PoisonError::into_inner);
+        if passes.len() >= MAX_PASSES {
+            passes.clear();
+        }
+        passes.insert(key.base());
+    }
+
+    /// Forgets that the response for `key` couldn't be cached.
+    pub fn unpass(&self, key: &CacheKey) {
+        self.passes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&key.base());
+    }
+}
+
+/// Held by the leader of a miss, the followers are woken up when it is dropped,
+/// whether the entry got stored or not.
+pub struct FlightGuard {
+    key: CacheKey,
+    misses: Arc<Mutex<HashMap<CacheKey, watch::Receiver<()>>>>,
+    _sender: watch::Sender<()>,
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.misses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
+
+/// Waits for the leader to be done, at most `timeout`.
+///
+/// Returns `false` when the timeout elapsed first.
+pub async fn wait(mut receiver: watch::Receiver<()>, timeout: Duration) -> bool {
+    // no value is ever sent, `changed` returns once the sender is dropped
+    tokio::time::timeout(timeout, receiver.changed())
+        .await
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::testing::{get, Origin};
+    use crate::cache::{CacheConfig, ResponseCache, ResponseCacheLayer, ResponseCacheService};
+    use axum::http::HeaderValue;
+    use std::time::{Duration, Instant};
+    use tokio::task::JoinSet;
+    use tower::Layer;
+
+    fn service(
+        origin: &Origin,
+        coalesce_timeout: Duration,
+    ) -> (ResponseCacheService<Origin>, ResponseCache) {
+        let config = CacheConfig::new()
+            .content_type("text/css", HeaderValue::from_static("public, max-age=300"))
+            .coalesce_timeout(coalesce_timeout);
+        let cache = ResponseCache::builder().build();
+        let service = ResponseCacheLayer::with_config(cache.clone(), config).layer(origin.clone());
+        (service, cache)
+    }
+
+    /// Sends `count` requests for the same URI, each `interval` after the previous one.
+    async fn concurrent(
+        service: &ResponseCacheService<Origin>,
+        count: usize,
+        interval: Duration,
+    ) -> Vec<String> {
+        let mut requests = JoinSet::new();
+        for _ in 0..count {
+            let service = service.clone();
+            requests.spawn(async move { get(&service, "/app.css").await.unwrap().0 });
+            tokio::time::sleep(interval).await;
+        }
+        let mut statuses = requests.join_all().await;
+        statuses.sort();
+        statuses
+    }
+
+    #[tokio::test]
+    async fn reaches_the_inner_service_once() {
+        let origin = Origin::new().delay(Duration::from_millis(50));
+        let (service, cache) = service(&origin, Duration::from_secs(10));
+
+        let statuses = concurrent(&service, 8, Duration::ZERO).await;
+        assert_eq!(origin.calls(), 1);
+        assert_eq!(
+            statuses.iter().filter(|status| *status == "MISS").count(),
+            1
+        );
+        assert_eq!(statuses.iter().filter(|status| *status == "HIT").count(), 7);
+        assert_eq!(cache.stats().await.coalesced, 7);
+    }
+
+    #[tokio::test]
+    async fn stops_waiting_after_the_timeout() {
+        let origin = Origin::new().delay(Duration::from_millis(200));
+        let (service, cache) = service(&origin, Duration::from_millis(10));
+
+        let statuses = concurrent(&service, 2, Duration::from_millis(5)).await;
+        assert_eq!(origin.calls(), 2);
+        assert_eq!(statuses, ["MISS", "MISS"]);
+        assert_eq!(cache.stats().await.coalesced, 0);
+    }
+
+    #[tokio::test]
+    async fn releases_the_followers_of_a_response_not_cacheable() {
+        let origin = Origin::new()
+            .delay(Duration::from_millis(50))
+            .cache_control("no-store");
+        let (service, cache) = service(&origin, Duration::from_secs(10));
+
+        let start = Instant::now();
+        let statuses = concurrent(&service, 4, Duration::from_millis(5)).await;
+        // the followers went on their own as soon as the leader gave up
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(origin.calls(), 4);
+        assert_eq!(statuses, ["BYPASS"; 4]);
+        assert_eq!(cache.stats().await.coalesced, 0);
+
+        // the resource is remembered as not cacheable, nobody waits anymore
+        let statuses = concurrent(&service, 2, Duration::ZERO).await;
+        assert_eq!(origin.calls(), 6);
+        assert_eq!(statuses, ["BYPASS"; 2]);
+    }
+}
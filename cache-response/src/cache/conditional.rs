@@ -0,0 +1,190 @@
+use axum::body::{Body, Bytes};
+use axum::http::{header, response::Parts, HeaderMap, HeaderValue, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::time::SystemTime;
+
+/// Headers kept on a `304 Not Modified`, see
+/// [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-15.4.5).
+const NOT_MODIFIED_HEADERS: [header::HeaderName; 6] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::VARY,
+];
+
+/// A strong entity tag made of the first 128 bits of the body SHA-256.
+pub fn etag(body: &Bytes) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    HeaderValue::from_str(&format!("\"{hex}\"")).expect("hex is ascii")
+}
+
+/// Adds the `ETag` and `Last-Modified` validators to a response about to be cached,
+/// keeping the ones already set by the inner service.
+pub fn add_validators(parts: &mut Parts, body: &Bytes) {
+    let headers = &mut parts.headers;
+    if !headers.contains_key(header::ETAG) {
+        headers.insert(header::ETAG, etag(body));
+    }
+    if !headers.contains_key(header::LAST_MODIFIED) {
+        let now = httpdate::fmt_http_date(SystemTime::now());
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&now).expect("http date is ascii"),
+        );
+    }
+}
+
+/// Whether the request preconditions let us answer `304 Not Modified`.
+///
+/// `If-None-Match` is evaluated first and, when present, `If-Modified-Since` is ignored
+/// as required by [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2).
+pub fn is_not_modified(request: &HeaderMap, cached: &HeaderMap) -> bool {
+    if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+        let Some(etag) = cached.get(header::ETAG) else {
+            return false;
+        };
+        return if_none_match
+            .to_str()
+            .map(|tags| etag_matches(tags, etag.as_bytes()))
+            .unwrap_or_default();
+    }
+
+    let since = request
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok());
+    let modified = cached
+        .get(header::LAST_MODIFIED)
+        .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Weak comparison of a `If-None-Match` list against an entity tag.
+fn etag_matches(if_none_match: &str, etag: &[u8]) -> bool {
+    let opaque = |tag: &[u8]| -> Vec<u8> { tag.strip_prefix(b"W/").unwrap_or(tag).to_vec() };
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || opaque(tag.as_bytes()) == etag)
+}
+
+/// Turns the parts of a cached response into a bodiless `304 Not Modified`.
+pub fn not_modified(parts: Parts) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    let headers = response.headers_mut();
+    for name in NOT_MODIFIED_HEADERS {
+        for value in parts.headers.get_all(&name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"0123456789abcdef0123456789abcdef\"";
+    const MODIFIED: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+    const BEFORE: &str = "Tue, 20 Oct 2015 07:28:00 GMT";
+    const AFTER: &str = "Thu, 22 Oct 2015 07:28:00 GMT";
+
+    fn cached() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, HeaderValue::from_static(ETAG));
+        headers.insert(header::LAST_MODIFIED, HeaderValue::from_static(MODIFIED));
+        headers
+    }
+
+    fn request(if_none_match: Option<&'static str>, since: Option<&'static str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(tags) = if_none_match {
+            headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static(tags));
+        }
+        if let Some(since) = since {
+            headers.insert(header::IF_MODIFIED_SINCE, HeaderValue::from_static(since));
+        }
+        headers
+    }
+
+    #[test]
+    fn etag_is_strong_and_stable() {
+        let body = Bytes::from_static(b"body { color: red }");
+        let etag = etag(&body);
+        assert!(etag.as_bytes().starts_with(b"\""));
+        assert_eq!(etag, super::etag(&body));
+        assert_ne!(etag, super::etag(&Bytes::from_static(b"body {}")));
+    }
+
+    #[test]
+    fn without_preconditions() {
+        assert!(!is_not_modified(&request(None, None), &cached()));
+    }
+
+    #[test]
+    fn if_none_match() {
+        assert!(is_not_modified(&request(Some(ETAG), None), &cached()));
+        assert!(is_not_modified(&request(Some("*"), None), &cached()));
+        let list = "\"other\", \"0123456789abcdef0123456789abcdef\"";
+        assert!(is_not_modified(&request(Some(list), None), &cached()));
+        let weak = "W/\"0123456789abcdef0123456789abcdef\"";
+        assert!(is_not_modified(&request(Some(weak), None), &cached()));
+        assert!(!is_not_modified(
+            &request(Some("\"other\""), None),
+            &cached()
+        ));
+    }
+
+    #[test]
+    fn if_none_match_without_etag() {
+        let mut cached = cached();
+        cached.remove(header::ETAG);
+        assert!(!is_not_modified(&request(Some(ETAG), None), &cached));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        assert!(is_not_modified(&request(None, Some(MODIFIED)), &cached()));
+        assert!(is_not_modified(&request(None, Some(AFTER)), &cached()));
+        assert!(!is_not_modified(&request(None, Some(BEFORE)), &cached()));
+        assert!(!is_not_modified(
+            &request(None, Some("yesterday")),
+            &cached()
+        ));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence() {
+        assert!(!is_not_modified(
+            &request(Some("\"other\""), Some(AFTER)),
+            &cached()
+        ));
+        assert!(is_not_modified(
+            &request(Some(ETAG), Some(BEFORE)),
+            &cached()
+        ));
+    }
+
+    #[test]
+    fn not_modified_has_no_body_headers() {
+        let mut response = Response::new(Body::empty());
+        response.headers_mut().extend(cached());
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/css"));
+        let (parts, _) = response.into_parts();
+
+        let response = not_modified(parts);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], ETAG);
+        assert!(!response.headers().contains_key(header::CONTENT_TYPE));
+        assert!(!response.headers().contains_key(header::LAST_MODIFIED));
+    }
+}
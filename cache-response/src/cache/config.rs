@@ -0,0 +1,420 @@
+use super::directives::CacheControl;
+use super::mime::{self, MediaRange};
+use super::{AdminConfig, Encoding, Expiry, PageConfig, RoutePolicies};
+use crate::assets::AssetManifest;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default name of the header telling whether a response comes from the cache.
+pub const DEFAULT_STATUS_HEADER: &str = "x-cache-status";
+
+/// Default `Cache-Control` value sent with cached assets, their URL can be reused by
+/// the next build.
+pub const DEFAULT_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// `Cache-Control` value sent with the content-hashed assets of the [`AssetManifest`].
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Default largest body the middleware buffers to store it, 32 MiB.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 32 * 1024 * 1024;
+
+/// Default largest body buffered before being sent on a cache miss, 256 KiB.
+pub const DEFAULT_STREAM_SIZE: usize = 256 * 1024;
+
+/// Default longest wait for a concurrent request populating the same entry.
+pub const DEFAULT_COALESCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default smallest body worth a compressed variant, 1 KiB.
+pub const DEFAULT_COMPRESS_MIN_SIZE: usize = 1024;
+
+/// The responses eligible for caching and the `Cache-Control` value they are served
+/// with, when the inner service doesn't set one.
+#[derive(Clone, Debug)]
+pub struct CacheRule {
+    pub pattern: RulePattern,
+    pub cache_control: HeaderValue,
+    /// Whether compressed variants are stored for this type.
+    pub compress: bool,
+    /// How long the responses stay fresh when they carry no `max-age` nor `s-maxage`,
+    /// the ones of `cache_control` by default, the
+    /// [`ResponseCache`](super::ResponseCache) time-to-live when `None`.
+    pub ttl: Option<Duration>,
+}
+
+/// What the responses of a [`CacheRule`] are recognized by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RulePattern {
+    /// The `Content-Type` of the response.
+    ContentType(MediaRange),
+    /// The extension of the request path, lowercase without the dot.
+    Extension(String),
+}
+
+impl RulePattern {
+    /// `.woff2` is an extension, anything else a content type pattern.
+    fn parse(name: &str) -> Option<Self> {
+        match name.strip_prefix('.') {
+            Some(extension) => Some(Self::Extension(extension.to_ascii_lowercase())),
+            None => MediaRange::parse(name).map(Self::ContentType),
+        }
+    }
+
+    pub fn matches(&self, path: &str, content_type: &str) -> bool {
+        match self {
+            Self::ContentType(range) => range.matches(content_type),
+            Self::Extension(extension) => {
+                mime::extension(path).is_some_and(|path| path == *extension)
+            }
+        }
+    }
+}
+
+/// Configuration of the [`ResponseCacheLayer`](super::ResponseCacheLayer).
+///
+/// The default configuration caches the `text/css`, `text/javascript`,
+/// `application/javascript`, `application/wasm`, `image/webp`, `font/ttf` and
+/// `image/x-icon` responses for five minutes, or one year when they are requested through their
+/// content-hashed URL, and stores brotli, zstd and gzip variants of all but webp :
+///
+/// ```ignore
+/// let config = CacheConfig::default()
+///     .content_type("image/*", HeaderValue::from_static("public, max-age=86400"))
+///     .extension("woff2", HeaderValue::from_static("public, max-age=86400"))
+///     .skip_compression(".woff2")
+///     .encodings([Encoding::Brotli, Encoding::Gzip])
+///     .precompressed_root(&leptos_options.site_root)
+///     .fingerprints(assets)
+///     .status_header(HeaderName::from_static("x-asset-cache"))
+///     .max_body_size(8 * 1024 * 1024);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    pub(crate) rules: Vec<CacheRule>,
+    pub(crate) status_header: HeaderName,
+    pub(crate) max_body_size: usize,
+    pub(crate) stream_size: usize,
+    pub(crate) coalesce_timeout: Duration,
+    pub(crate) stale_while_revalidate: Duration,
+    pub(crate) stale_if_error: Duration,
+    pub(crate) server_timing: bool,
+    pub(crate) override_cache_control: bool,
+    pub(crate) admin: Option<AdminConfig>,
+    pub(crate) pages: Option<PageConfig>,
+    pub(crate) routes: RoutePolicies,
+    pub(crate) metrics: Option<String>,
+    pub(crate) prewarm: bool,
+    pub(crate) fingerprints: AssetManifest,
+    pub(crate) encodings: Vec<Encoding>,
+    pub(crate) compress_min_size: usize,
+    pub(crate) precompressed_root: Option<PathBuf>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let cache_control = HeaderValue::from_static(DEFAULT_CACHE_CONTROL);
+        [
+            "text/css",
+            "text/javascript",
+            "application/javascript",
+            "application/wasm",
+            "image/webp",
+            "font/ttf",
+            "image/x-icon",
+        ]
+        .into_iter()
+        .fold(Self::new(), |config, content_type| {
+            config.content_type(content_type, cache_control.clone())
+        })
+        // already compressed
+        .skip_compression("image/webp")
+    }
+}
+
+impl CacheConfig {
+    /// A configuration without any rule, caching nothing until the content types or
+    /// extensions are opted in one by one. The asset types cached by
+    /// [`CacheConfig::default`] aren't part of it.
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            status_header: HeaderName::from_static(DEFAULT_STATUS_HEADER),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            stream_size: DEFAULT_STREAM_SIZE,
+            coalesce_timeout: DEFAULT_COALESCE_TIMEOUT,
+            stale_while_revalidate: Duration::ZERO,
+            stale_if_error: Duration::ZERO,
+            server_timing: false,
+            override_cache_control: false,
+            admin: None,
+            pages: None,
+            routes: RoutePolicies::default(),
+            metrics: None,
+            prewarm: true,
+            fingerprints: AssetManifest::default(),
+            encodings: Encoding::COMPRESSED.to_vec(),
+            compress_min_size: DEFAULT_COMPRESS_MIN_SIZE,
+            precompressed_root: None,
+        }
+    }
+
+    /// Caches responses whose `Content-Type` matches `pattern`, like `text/css` or
+    /// `image/*`, and serves them with `cache_control`, unless they set their own.
+    /// A pattern already configured gets its value replaced.
+    ///
+    /// # Panics
+    ///
+    /// When `pattern` isn't a `type/subtype` pattern, see [`MediaRange`].
+    pub fn content_type(self, pattern: &str, cache_control: HeaderValue) -> Self {
+        let range = MediaRange::parse(pattern)
+            .unwrap_or_else(|| panic!("invalid content type pattern {pattern:?}"));
+        self.rule(RulePattern::ContentType(range), cache_control)
+    }
+
+    /// Caches the responses to the paths ending with `.extension`, whatever their
+    /// `Content-Type`, and serves them with `cache_control`, unless they set their own.
+    pub fn extension(self, extension: &str, cache_control: HeaderValue) -> Self {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.rule(RulePattern::Extension(extension), cache_control)
+    }
+
+    fn rule(mut self, pattern: RulePattern, cache_control: HeaderValue) -> Self {
+        // stored for as long as the clients are told, until `ttl` says otherwise
+        let headers = HeaderMap::from_iter([(header::CACHE_CONTROL, cache_control.clone())]);
+        let ttl = CacheControl::parse(&headers)
+            .and_then(|directives| directives.s_maxage.or(directives.max_age));
+        self.rules.retain(|rule| rule.pattern != pattern);
+        self.rules.push(CacheRule {
+            pattern,
+            cache_control,
+            compress: true,
+            ttl,
+        });
+        self
+    }
+
+    /// The rules configured for `name`, a content type pattern or an extension with
+    /// its dot, like `.woff2`.
+    fn rules_named<'a>(&'a mut self, name: &str) -> impl Iterator<Item = &'a mut CacheRule> {
+        let pattern = RulePattern::parse(name);
+        self.rules
+            .iter_mut()
+            .filter(move |rule| pattern.as_ref() == Some(&rule.pattern))
+    }
+
+    /// How long the responses of an already configured pattern, `image/webp`, or
+    /// extension, `.woff2`, stay fresh. Defaults to the `s-maxage` or `max-age` of the
+    /// `Cache-Control` of the rule.
+    pub fn ttl(mut self, name: &str, ttl: Duration) -> Self {
+        self.rules_named(name).for_each(|rule| rule.ttl = Some(ttl));
+        self
+    }
+
+    /// For how long after expiring an entry is still served, once per refresh, while a
+    /// background request fetches a fresh response through the inner service.
+    pub fn stale_while_revalidate(mut self, stale_while_revalidate: Duration) -> Self {
+        self.stale_while_revalidate = stale_while_revalidate;
+        self
+    }
+
+    /// For how long after expiring an entry is served when the inner service fails
+    /// to give a fresh response.
+    pub fn stale_if_error(mut self, stale_if_error: Duration) -> Self {
+        self.stale_if_error = stale_if_error;
+        self
+    }
+
+    /// Never stores compressed variants of an already configured pattern or extension.
+    pub fn skip_compression(mut self, name: &str) -> Self {
+        self.rules_named(name)
+            .for_each(|rule| rule.compress = false);
+        self
+    }
+
+    /// Bodies larger than this, or of unknown length, are streamed to the client while
+    /// being stored. Smaller ones are buffered first so the first response is already
+    /// validated and compressed.
+    pub fn stream_size(mut self, stream_size: usize) -> Self {
+        self.stream_size = stream_size;
+        self
+    }
+
+    /// How long a miss waits for a concurrent request populating the same entry before
+    /// reaching the inner service on its own. `Duration::ZERO` disables the wait.
+    pub fn coalesce_timeout(mut self, coalesce_timeout: Duration) -> Self {
+        self.coalesce_timeout = coalesce_timeout;
+        self
+    }
+
+    /// Adds `Server-Timing` entries with the cache status and the time taken by the
+    /// response, the inner service included on a miss, shown in the browser devtools.
+    /// Disabled by default.
+    pub fn server_timing(mut self, server_timing: bool) -> Self {
+        self.server_timing = server_timing;
+        self
+    }
+
+    /// Replaces the `Cache-Control` set by the inner service with the one of the rule,
+    /// and ignores its `max-age` and `s-maxage`. Disabled by default, a `private` or
+    /// `no-store` response is never stored either way.
+    pub fn override_cache_control(mut self, override_cache_control: bool) -> Self {
+        self.override_cache_control = override_cache_control;
+        self
+    }
+
+    /// Enables the admin endpoints, see [`AdminConfig`].
+    pub fn admin(mut self, admin: AdminConfig) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    /// Serves the metrics of the cache at `path`, in the Prometheus text format,
+    /// usually [`DEFAULT_METRICS_PATH`](super::metrics::DEFAULT_METRICS_PATH).
+    ///
+    /// With an [`admin`](Self::admin) configuration, the scrapes must carry its secret.
+    /// Without one, the metrics are served to anyone : only bind the server to an
+    /// internal address then.
+    pub fn metrics(mut self, path: impl Into<String>) -> Self {
+        self.metrics = Some(path.into());
+        self
+    }
+
+    /// Caches the rendered pages too, see [`PageConfig`].
+    pub fn pages(mut self, pages: PageConfig) -> Self {
+        self.pages = Some(pages);
+        self
+    }
+
+    /// The cache policies of the routes, taking precedence over the content type rules.
+    pub fn routes(mut self, routes: RoutePolicies) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Whether [`warm_dir`](super::warm::warm_dir) loads files into the cache at startup,
+    /// enabled by default.
+    pub fn prewarm(mut self, prewarm: bool) -> Self {
+        self.prewarm = prewarm;
+        self
+    }
+
+    /// The content-hashed URLs served, as [`IMMUTABLE_CACHE_CONTROL`], from the file
+    /// they stand for.
+    pub fn fingerprints(mut self, fingerprints: AssetManifest) -> Self {
+        self.fingerprints = fingerprints;
+        self
+    }
+
+    /// Encodings offered to clients, in order of preference. An empty list disables
+    /// compression and the responses are only stored as sent by the inner service.
+    pub fn encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        self.encodings = encodings
+            .into_iter()
+            .filter(|encoding| *encoding != Encoding::Identity)
+            .collect();
+        self
+    }
+
+    /// Bodies smaller than this are only stored uncompressed.
+    pub fn compress_min_size(mut self, compress_min_size: usize) -> Self {
+        self.compress_min_size = compress_min_size;
+        self
+    }
+
+    /// Directory searched for pre-compressed siblings (`app.wasm.br`, `app.wasm.gz`, ...)
+    /// before compressing a body in memory, usually the cargo-leptos `site-root`.
+    pub fn precompressed_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.precompressed_root = Some(root.into());
+        self
+    }
+
+    /// Name of the header marking responses served by the middleware.
+    pub fn status_header(mut self, status_header: HeaderName) -> Self {
+        self.status_header = status_header;
+        self
+    }
+
+    /// Responses with a larger body are passed through without being cached.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    pub fn rules(&self) -> &[CacheRule] {
+        &self.rules
+    }
+
+    /// How long an entry matching `rule` lives in the cache.
+    pub fn expiry(&self, rule: &CacheRule) -> Expiry {
+        Expiry {
+            ttl: rule.ttl,
+            grace: self.grace(),
+        }
+    }
+
+    /// How long expired entries are kept to be served stale.
+    pub fn grace(&self) -> Duration {
+        self.stale_while_revalidate.max(self.stale_if_error)
+    }
+
+    /// Finds the first rule matching a response `Content-Type` value or the extension
+    /// of its `path`, the [`PageConfig`] one for `text/html` when the page cache is
+    /// enabled.
+    pub fn rule_for(&self, path: &str, content_type: &str) -> Option<&CacheRule> {
+        self.rules
+            .iter()
+            .chain(self.pages.as_ref().map(PageConfig::rule))
+            .find(|rule| rule.pattern.matches(path, content_type))
+    }
+
+    /// The rule of a response at `path`, the one of its route when its policy is
+    /// public, otherwise the one of its content type.
+    pub fn rule_for_route(&self, path: &str, content_type: &str) -> Option<&CacheRule> {
+        self.routes
+            .rule(path)
+            .or_else(|| self.rule_for(path, content_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_default_types() {
+        let config = CacheConfig::default();
+        let rule = config.rule_for("/pkg/app.js", "text/javascript; charset=utf-8");
+        assert!(rule.is_some_and(|rule| rule.compress));
+        let rule = config.rule_for("/pkg/hero.webp", "image/webp");
+        assert!(rule.is_some_and(|rule| !rule.compress));
+        assert!(config
+            .rule_for("/data", "application/x-not-really-css-thing")
+            .is_none());
+        assert!(config.rule_for("/", "text/html").is_none());
+        // the unhashed assets expire with the `Cache-Control` of their rule
+        let rule = config.rule_for("/pkg/app.css", "text/css").unwrap();
+        assert_eq!(rule.ttl, Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn matches_the_extensions() {
+        let cache_control = HeaderValue::from_static("public, max-age=86400");
+        let config = CacheConfig::new()
+            .content_type("image/*", cache_control.clone())
+            .extension(".WOFF2", cache_control)
+            .skip_compression(".woff2")
+            .ttl("image/*", Duration::from_secs(60));
+        let font = config.rule_for("/pkg/font.woff2", "application/octet-stream");
+        let day = Some(Duration::from_secs(86400));
+        assert!(font.is_some_and(|rule| !rule.compress && rule.ttl == day));
+        let image = config.rule_for("/logo", "image/svg+xml");
+        assert!(image.is_some_and(|rule| rule.ttl == Some(Duration::from_secs(60))));
+        assert!(config.rule_for("/font.woff", "font/woff").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid content type pattern")]
+    fn rejects_bare_subtypes() {
+        _ = CacheConfig::new().content_type("css", HeaderValue::from_static("no-cache"));
+    }
+}
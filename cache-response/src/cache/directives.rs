@@ -0,0 +1,151 @@
+use axum::http::{header, HeaderMap};
+use std::time::Duration;
+
+/// The `Cache-Control` directives the middleware understands, see
+/// [RFC 9111](https://www.rfc-editor.org/rfc/rfc9111#section-5.2).
+///
+/// Unknown directives are ignored, as well as the arguments of `private` and
+/// `no-cache`, which then apply to the whole response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub private: bool,
+    pub public: bool,
+    pub immutable: bool,
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+}
+
+impl CacheControl {
+    /// Parses every `Cache-Control` header, `None` when there is none.
+    pub fn parse(headers: &HeaderMap) -> Option<Self> {
+        let mut values = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .peekable();
+        values.peek()?;
+
+        let mut directives = Self::default();
+        for directive in values.flat_map(|value| value.split(',')) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            let seconds = || argument?.parse().ok().map(Duration::from_secs);
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-cache" => directives.no_cache = true,
+                "no-store" => directives.no_store = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "immutable" => directives.immutable = true,
+                // the first occurrence wins, the others are likely mistakes
+                "max-age" => directives.max_age = directives.max_age.or_else(seconds),
+                "s-maxage" => directives.s_maxage = directives.s_maxage.or_else(seconds),
+                _ => {}
+            }
+        }
+        Some(directives)
+    }
+
+    /// Whether a response may be stored in a cache shared by all clients.
+    pub fn is_shareable(&self) -> bool {
+        !self.private && !self.no_store
+    }
+
+    /// How long a shared cache may serve the response, `s-maxage` taking precedence
+    /// over `max-age`. A `no-cache` response must be revalidated on each request.
+    pub fn ttl(&self) -> Option<Duration> {
+        match self.no_cache {
+            true => Some(Duration::ZERO),
+            false => self.s_maxage.or(self.max_age),
+        }
+    }
+}
+
+/// Whether the client asks for a response fresh from the inner service, with a
+/// `no-cache` or `no-store` request, or a `Pragma: no-cache` one without `Cache-Control`.
+pub fn bypasses(req_headers: &HeaderMap) -> bool {
+    match CacheControl::parse(req_headers) {
+        Some(directives) => directives.no_cache || directives.no_store,
+        None => req_headers
+            .get_all(header::PRAGMA)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: header::HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn parse(values: &[&'static str]) -> Option<CacheControl> {
+        CacheControl::parse(&headers(header::CACHE_CONTROL, values))
+    }
+
+    #[test]
+    fn parses_the_directives() {
+        let directives = parse(&["Public, max-age=60", "s-maxage=\"300\", immutable"]).unwrap();
+        assert_eq!(
+            directives,
+            CacheControl {
+                public: true,
+                immutable: true,
+                max_age: Some(Duration::from_secs(60)),
+                s_maxage: Some(Duration::from_secs(300)),
+                ..CacheControl::default()
+            }
+        );
+        assert_eq!(directives.ttl(), Some(Duration::from_secs(300)));
+        assert!(directives.is_shareable());
+        assert_eq!(parse(&[]), None);
+    }
+
+    #[test]
+    fn keeps_the_first_max_age() {
+        let directives = parse(&["max-age=60, max-age=3600, max-age=invalid"]).unwrap();
+        assert_eq!(directives.ttl(), Some(Duration::from_secs(60)));
+        assert_eq!(parse(&["max-age=soon"]).unwrap().ttl(), None);
+    }
+
+    #[test]
+    fn private_responses_are_not_shareable() {
+        assert!(!parse(&["private=\"set-cookie\", max-age=60"])
+            .unwrap()
+            .is_shareable());
+        assert!(!parse(&["no-store"]).unwrap().is_shareable());
+        assert_eq!(
+            parse(&["no-cache, max-age=60"]).unwrap().ttl(),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn bypasses_fresh_requests() {
+        assert!(bypasses(&headers(header::CACHE_CONTROL, &["no-cache"])));
+        assert!(bypasses(&headers(header::CACHE_CONTROL, &["NO-STORE"])));
+        assert!(!bypasses(&headers(header::CACHE_CONTROL, &["max-age=0"])));
+        assert!(bypasses(&headers(header::PRAGMA, &["no-cache"])));
+        assert!(!bypasses(&HeaderMap::new()));
+
+        // `Pragma` is only a fallback for the clients without `Cache-Control`
+        let mut both = headers(header::PRAGMA, &["no-cache"]);
+        both.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("max-age=60"),
+        );
+        assert!(!bypasses(&both));
+    }
+}
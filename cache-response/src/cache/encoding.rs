@@ -0,0 +1,152 @@
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap, HeaderValue};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// A content coding the cache can store variants for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// The compressed encodings, in the order they are preferred on a tie.
+    pub const COMPRESSED: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    /// The token used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// The encoding named `token`, as returned by [`as_str`](Self::as_str).
+    pub fn from_token(token: &str) -> Option<Self> {
+        [Encoding::Identity]
+            .into_iter()
+            .chain(Encoding::COMPRESSED)
+            .find(|encoding| encoding.as_str().eq_ignore_ascii_case(token))
+    }
+
+    /// Extension of the pre-compressed sibling file, like `app.wasm.br`.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gz"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Zstd => Some("zst"),
+        }
+    }
+
+    pub fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(self.as_str())
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Picks the encoding to serve among `available` from the `Accept-Encoding` header.
+///
+/// The highest quality wins, ties go to the first one in `available`. Without
+/// `Accept-Encoding` or when nothing is acceptable, the body is sent as is.
+pub fn negotiate(request: &HeaderMap, available: &[Encoding]) -> Encoding {
+    let accepted: Vec<(&str, f32)> = request
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().filter(|name| !name.is_empty())?;
+            let quality = params
+                .find_map(|param| param.strip_prefix("q=")?.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((name, quality))
+        })
+        .collect();
+
+    let quality = |encoding: Encoding| {
+        let explicit = accepted
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(encoding.as_str()));
+        let wildcard = accepted.iter().find(|(name, _)| *name == "*");
+        explicit.or(wildcard).map_or(0.0, |(_, quality)| *quality)
+    };
+
+    let mut best = (Encoding::Identity, 0.0);
+    for &encoding in available {
+        let quality = quality(encoding);
+        if quality > best.1 {
+            best = (encoding, quality);
+        }
+    }
+    best.0
+}
+
+/// Compresses `body`, meant to run on a blocking thread.
+pub fn compress(encoding: Encoding, body: &[u8]) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(body.to_vec()),
+        Encoding::Gzip => {
+            let level = flate2::Compression::best();
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut compressed = Vec::new();
+            {
+                // quality 9 keeps the first request of a large wasm bundle reasonably fast
+                let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 9, 22);
+                encoder.write_all(body)?;
+            }
+            Ok(compressed)
+        }
+        Encoding::Zstd => zstd::encode_all(body, 15),
+    }
+}
+
+/// Reads the pre-compressed sibling of the file served at `path` under `root`,
+/// for instance `<root>/pkg/app.wasm.br`.
+pub async fn read_sibling(root: &Path, path: &str, encoding: Encoding) -> Option<Bytes> {
+    let extension = encoding.extension()?;
+    let relative = PathBuf::from(path.trim_start_matches('/'));
+    // never leave the root
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let mut file = root.join(relative).into_os_string();
+    file.push(".");
+    file.push(extension);
+    tokio::fs::read(file).await.ok().map(Bytes::from)
+}
+
+/// Appends `Accept-Encoding` to the `Vary` header if it isn't there yet.
+pub fn vary_on_accept_encoding(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|name| {
+            let name = name.trim();
+            name == "*" || name.eq_ignore_ascii_case(header::ACCEPT_ENCODING.as_str())
+        });
+    if !listed {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
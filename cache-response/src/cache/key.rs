@@ -0,0 +1,158 @@
+use super::Encoding;
+use axum::http::uri::PathAndQuery;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Uri};
+use std::fmt;
+
+/// Identifies a stored response.
+///
+/// `HEAD` requests share the entries of `GET`, the headers listed in the `Vary` of the
+/// stored response are part of the key, except `Accept-Encoding` which is replaced by
+/// the negotiated [`Encoding`]. Only the path and the query of the URI are kept, an
+/// absolute-form or HTTP/2 request shares the entries of the other requests.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    method: Method,
+    uri: PathAndQuery,
+    encoding: Encoding,
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+}
+
+impl CacheKey {
+    /// The key of `uri` for a `method`, `None` if responses to the method are never cached.
+    pub fn new(method: &Method, uri: &Uri) -> Option<Self> {
+        if method != Method::GET && method != Method::HEAD {
+            return None;
+        }
+        Some(Self {
+            method: Method::GET,
+            uri: uri
+                .path_and_query()
+                .cloned()
+                .unwrap_or_else(|| PathAndQuery::from_static("/")),
+            encoding: Encoding::Identity,
+            vary: Vec::new(),
+        })
+    }
+
+    pub fn from_request<B>(req: &Request<B>) -> Option<Self> {
+        Self::new(req.method(), req.uri())
+    }
+
+    /// The same key for another encoded variant.
+    pub fn with_encoding(&self, encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            ..self.clone()
+        }
+    }
+
+    /// Adds the values of the `names` request headers to the key.
+    pub fn with_vary(mut self, names: &[HeaderName], request: &HeaderMap) -> Self {
+        self.vary = names
+            .iter()
+            .map(|name| (name.clone(), request.get(name).cloned()))
+            .collect();
+        self
+    }
+
+    /// The key without encoding nor `Vary` values, shared by all the variants of a resource.
+    pub fn base(&self) -> Self {
+        Self {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+            encoding: Encoding::Identity,
+            vary: Vec::new(),
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path and the query of the resource.
+    pub fn uri(&self) -> &str {
+        self.uri.as_str()
+    }
+
+    /// The path of the resource, without the query.
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The values of the request headers the stored response varies on.
+    pub fn vary(&self) -> &[(HeaderName, Option<HeaderValue>)] {
+        &self.vary
+    }
+
+    /// The same key with the given `Vary` values, like ones read back from a store.
+    pub fn with_vary_values(mut self, vary: Vec<(HeaderName, Option<HeaderValue>)>) -> Self {
+        self.vary = vary;
+        self
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.uri)?;
+        if self.encoding != Encoding::Identity {
+            write!(f, " {}", self.encoding)?;
+        }
+        for (name, value) in &self.vary {
+            let value = value.as_ref().and_then(|value| value.to_str().ok());
+            write!(f, " {name}={}", value.unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
+
+/// The request headers a response varies on, `Accept-Encoding` being left out.
+///
+/// Returns `None` for `Vary: *`, such a response can't be matched to any request.
+pub fn vary_names(response: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+    let values = response
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+    for name in values {
+        if name == "*" {
+            return None;
+        }
+        let Ok(name) = HeaderName::try_from(name) else {
+            continue;
+        };
+        if name != header::ACCEPT_ENCODING && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    Some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_on_the_path_and_the_query() {
+        let origin = CacheKey::new(&Method::GET, &Uri::from_static("/app.css?v=1")).unwrap();
+        let absolute = Uri::from_static("http://example.com/app.css?v=1");
+        assert_eq!(
+            CacheKey::new(&Method::HEAD, &absolute),
+            Some(origin.clone())
+        );
+        assert_eq!(origin.uri(), "/app.css?v=1");
+        assert_eq!(origin.path(), "/app.css");
+
+        let host = CacheKey::new(&Method::GET, &Uri::from_static("http://example.com")).unwrap();
+        assert_eq!(host.uri(), "/");
+        assert_eq!(CacheKey::new(&Method::POST, &absolute), None);
+    }
+}
@@ -0,0 +1,869 @@
+use super::body::{Commit, TeeBody};
+use super::coalesce::{self, Flight, FlightGuard};
+use super::directives::{self, CacheControl};
+use super::encoding::{self, Encoding};
+use super::key::{self, CacheKey};
+use super::status::CacheStatus;
+use super::{admin, metrics};
+use super::{
+    conditional, pages, range, CacheConfig, CachedResponse, Expiry, Lookup, ResponseCache,
+    IMMUTABLE_CACHE_CONTROL,
+};
+use axum::body::{to_bytes, Body, Bytes, HttpBody};
+use axum::http::{
+    header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service, ServiceExt};
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Caches the responses matching a [`CacheConfig`] in a [`ResponseCache`].
+///
+/// ```ignore
+/// let cache = ResponseCache::builder().max_entries(200).build();
+/// let app = Router::new().layer(ResponseCacheLayer::new(cache));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ResponseCacheLayer {
+    cache: ResponseCache,
+    config: Arc<CacheConfig>,
+}
+
+impl ResponseCacheLayer {
+    /// A layer storing into `cache` with the default [`CacheConfig`].
+    pub fn new(cache: ResponseCache) -> Self {
+        Self::with_config(cache, CacheConfig::default())
+    }
+
+    pub fn with_config(cache: ResponseCache, config: CacheConfig) -> Self {
+        Self {
+            cache,
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<S> Layer<S> for ResponseCacheLayer {
+    type Service = ResponseCacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseCacheService {
+            inner,
+            cache: self.cache.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResponseCacheService<T> {
+    inner: T,
+    cache: ResponseCache,
+    config: Arc<CacheConfig>,
+}
+
+impl<T> Service<Request<Body>> for ResponseCacheService<T>
+where
+    T: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = T::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    /// - **Cache Hit :** If the requested file is already cached, the cached response is returned with its original status. A `Range` request gets the matching bytes with a [*206 Partial Content* status code](https://developer.mozilla.org/en-US/docs/Web/HTTP/Reference/Status/206), or a *416 Range Not Satisfiable* when none of them exist.
+    /// - **Revalidation :** Cached responses carry an `ETag` and a `Last-Modified`, a request whose `If-None-Match` or `If-Modified-Since` still matches gets a bodiless *304 Not Modified*.
+    /// - **Compression :** The body is stored once per encoding negotiated with `Accept-Encoding` (brotli, zstd, gzip), taken from a pre-compressed sibling file or compressed on the first request.
+    /// - **Fingerprints :** The content-hashed URLs of the [`AssetManifest`](crate::assets::AssetManifest) are served from the file they stand for, as `immutable` for one year, the other assets for a short time.
+    /// - **Pages :** With a [`PageConfig`](super::PageConfig), the rendered pages are cached too, except for the requests carrying a session cookie and the excluded routes.
+    /// - **Directives :** A request with `Cache-Control: no-cache` or `no-store`, or `Pragma: no-cache`, bypasses the cache. The `max-age` and `s-maxage` of a response set how long it is stored, and its `Cache-Control` is only replaced by the one of its rule when [`CacheConfig::override_cache_control`] is enabled.
+    /// - **Routes :** The [`CachePolicy`](super::CachePolicy) of a route takes precedence over the content type rules, its `no-store` or `private` responses never reach the cache.
+    /// - **Keys :** Only `GET` and `HEAD` requests are cached, `HEAD` being served from the `GET` entry without a body. The request headers named in the `Vary` of the stored response are part of the [`CacheKey`].
+    /// - **Streaming :** Large bodies are streamed to the client while being stored, the entry is only committed once the whole body went through without exceeding the size limit.
+    /// - **Coalescing :** Concurrent misses for the same key wait for the first one to populate the entry, at most [`CacheConfig::coalesce_timeout`], instead of all reaching the inner service.
+    /// - **Expiration :** Entries expire after the time-to-live of their content type. An expired entry is served `STALE` once while a background request refreshes it (stale-while-revalidate), or when the inner service fails (stale-if-error).
+    /// - **Metrics :** With [`CacheConfig::metrics`], the counters and gauges of the cache are served in the Prometheus text format, to the requests carrying the admin secret when an [`AdminConfig`](super::AdminConfig) is set.
+    /// - **Status :** The status header (`x-cache-status` by default) tells whether the response is a `HIT`, a `MISS`, a `BYPASS`, `STALE` or `EXPIRED`. Hits carry an `Age` and, when enabled, the `Server-Timing` entries report the status and the `total` time of the response, the inner service included on a miss.
+    /// - **Cache Miss :** If the file is not cached but its `Content-Type` matches a [`CacheConfig`] rule, the response is cached, unless it sets a cookie or is `private` / `no-store`. This cached response will be returned on subsequent requests.
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // the ready service is the one polled, keep it and leave a clone in its place
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let cache = self.cache.clone();
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            if log::log_enabled!(log::Level::Debug) {
+                let stats = cache.stats().await;
+                log::debug!(
+                    "the cache size is {} / {} ({} bytes)",
+                    stats.entries,
+                    cache.capacity(),
+                    stats.bytes
+                );
+            }
+
+            if let Some(admin) = config.admin.as_ref() {
+                if let Some(route) = admin.route(req.uri().path()) {
+                    let route = route.to_owned();
+                    return Ok(admin::handle(inner, &cache, &config, admin, &route, req).await);
+                }
+            }
+
+            if config.metrics.as_deref() == Some(req.uri().path()) {
+                let admin = config.admin.as_ref();
+                if let Some(rejection) = admin.and_then(|admin| admin.reject(req.headers())) {
+                    return Ok(rejection);
+                }
+                return Ok(metrics::handle(&cache).await);
+            }
+
+            let start = Instant::now();
+            let (mut response, status) = respond(inner, &cache, &config, req).await?;
+            cache.record(status, content_type(&response));
+
+            let headers = response.headers_mut();
+            headers.insert(config.status_header.clone(), status.header_value());
+            if config.server_timing {
+                headers.append(SERVER_TIMING, status.server_timing(start.elapsed()));
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Answers a request from the cache or through the inner service.
+pub(super) async fn respond<T>(
+    mut inner: T,
+    cache: &ResponseCache,
+    config: &Arc<CacheConfig>,
+    mut req: Request<Body>,
+) -> Result<(Response<Body>, CacheStatus), T::Error>
+where
+    T: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    // only `GET` and `HEAD` are cached
+    let Some(base) = CacheKey::from_request(&req) else {
+        return Ok((inner.call(req).await?, CacheStatus::Bypass));
+    };
+    // a content-hashed URL is stored under its own key, but served from its file
+    let logical = config.fingerprints.logical(req.uri().path());
+    if let Some(logical) = logical {
+        *req.uri_mut() = logical_uri(req.uri(), logical);
+    }
+    let head = req.method() == Method::HEAD;
+    let path = req.uri().path().to_owned();
+    // the routes kept out of the shared cache only get the header of their policy
+    if let Some(policy) = config.routes.policy(&path) {
+        if !policy.is_shared() {
+            let mut response = inner.call(req).await?;
+            let headers = response.headers_mut();
+            headers.insert(header::CACHE_CONTROL, policy.header_value());
+            return Ok((response, CacheStatus::Bypass));
+        }
+    }
+    // the client asks for a fresh response, it still gets the headers of a cached one
+    if directives::bypasses(req.headers()) {
+        let mut response = inner.call(req).await?;
+        let cache_control = match logical {
+            Some(_) => Some(HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL)),
+            None => config
+                .routes
+                .policy(&path)
+                .map(|policy| policy.header_value()),
+        };
+        if let Some(cache_control) = cache_control {
+            let headers = response.headers_mut();
+            headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+        return Ok((response, CacheStatus::Bypass));
+    }
+    let req_headers = req.headers().clone();
+    let key = base
+        .clone()
+        .with_vary(&cache.vary(&base).await, &req_headers);
+    let encoding = encoding::negotiate(&req_headers, &config.encodings);
+
+    let encoded = match encoding {
+        Encoding::Identity => None,
+        encoding => cache.get(&key.with_encoding(encoding)).await,
+    };
+
+    // the pages rendered for a session are neither served from the cache nor stored,
+    // whichever of their variants is cached
+    let private = config
+        .pages
+        .as_ref()
+        .is_some_and(|pages| pages.has_session(&req_headers));
+    if private {
+        let page = match &encoded {
+            Some(cached) => pages::is_page(&cached.parts.headers),
+            None => match cache.lookup(&key).await {
+                Lookup::Hit(cached) | Lookup::Stale(cached) => {
+                    pages::is_page(&cached.parts.headers)
+                }
+                _ => false,
+            },
+        };
+        if page {
+            return Ok((inner.call(req).await?, CacheStatus::Bypass));
+        }
+    }
+
+    // if `cached`, return `cached`
+
+    if let Some(cached) = encoded {
+        return Ok(serve(&req_headers, cached, head, CacheStatus::Hit));
+    }
+
+    let mut stale = None;
+    let (stored, miss) = match cache.lookup(&key).await {
+        Lookup::Hit(cached) => (Some(cached), CacheStatus::Hit),
+        Lookup::Stale(cached) if cached.expired_for() < config.stale_while_revalidate => {
+            // served stale while a single background request refreshes the entry
+            if let Flight::Leader(guard) = cache.join(&key) {
+                let req = upstream_request(&req);
+                revalidate(inner, cache.clone(), config.clone(), base, req, guard);
+            }
+            return Ok(serve(&req_headers, cached, head, CacheStatus::Stale));
+        }
+        Lookup::Stale(cached) => {
+            stale = Some(cached);
+            (None, CacheStatus::Expired)
+        }
+        Lookup::Expired => (None, CacheStatus::Expired),
+        Lookup::Miss => (None, CacheStatus::Miss),
+    };
+    // a `HEAD` response has no body to store
+    if stored.is_none() && head {
+        return Ok((inner.call(req).await?, CacheStatus::Bypass));
+    }
+
+    let (key, identity) = match stored {
+        Some(cached) => (key, cached),
+        None => {
+            // only one request populates a missing entry, the others wait for it,
+            // except the private ones which may not get the shared response
+            let flight = match private {
+                true => Flight::Pass,
+                false => cache.join(&key),
+            };
+            let (guard, coalesced) = match flight {
+                Flight::Leader(guard) => (Some(guard), None),
+                Flight::Pass => (None, None),
+                Flight::Follower(receiver) => {
+                    if !coalesce::wait(receiver, config.coalesce_timeout).await {
+                        log::debug!("stopped waiting for {key} to be cached");
+                    }
+                    let key = base
+                        .clone()
+                        .with_vary(&cache.vary(&base).await, &req_headers);
+                    (None, cache.get(&key).await.map(|cached| (key, cached)))
+                }
+            };
+
+            match coalesced {
+                Some(stored) => {
+                    cache.record_coalesced();
+                    stored
+                }
+                None => {
+                    let response = match inner.call(upstream_request(&req)).await {
+                        Ok(response) if !response.status().is_server_error() => response,
+                        // the expired entry is better than an error
+                        result => match stale
+                            .filter(|cached| cached.expired_for() < config.stale_if_error)
+                        {
+                            Some(cached) => {
+                                log::debug!("serving {key} stale, the inner service failed");
+                                return Ok(serve(&req_headers, cached, head, CacheStatus::Stale));
+                            }
+                            None => result?,
+                        },
+                    };
+                    let filled =
+                        fill(cache, config, &base, &req_headers, response, guard, private).await;
+                    match filled {
+                        Filled::Stored(key, cached) => {
+                            let cached = match encoding {
+                                Encoding::Identity => cached,
+                                encoding => {
+                                    variant(cache, config, &key, &path, cached, encoding).await
+                                }
+                            };
+                            return Ok(serve(&req_headers, cached, head, miss));
+                        }
+                        Filled::Streaming(response) => return Ok((response, miss)),
+                        Filled::Passed(response) => return Ok((response, CacheStatus::Bypass)),
+                    }
+                }
+            }
+        }
+    };
+
+    let cached = match encoding {
+        Encoding::Identity => identity,
+        encoding => variant(cache, config, &key, &path, identity, encoding).await,
+    };
+
+    Ok(serve(&req_headers, cached, head, CacheStatus::Hit))
+}
+
+/// `uri` with the path of the file a content-hashed URL stands for.
+fn logical_uri(uri: &Uri, logical: &str) -> Uri {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{logical}?{query}"),
+        None => logical.to_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = path_and_query.parse().ok();
+    Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+}
+
+/// The request sent to the inner service to populate an entry.
+///
+/// The cache answers the preconditions, the ranges and compresses the variants itself,
+/// so the inner service is asked for the full raw body.
+fn upstream_request(req: &Request<Body>) -> Request<Body> {
+    let mut upstream = Request::new(Body::empty());
+    *upstream.method_mut() = Method::GET;
+    *upstream.uri_mut() = req.uri().clone();
+    *upstream.version_mut() = req.version();
+    *upstream.headers_mut() = req.headers().clone();
+    *upstream.extensions_mut() = req.extensions().clone();
+    let headers = upstream.headers_mut();
+    for name in [
+        header::ACCEPT_ENCODING,
+        header::IF_MATCH,
+        header::IF_NONE_MATCH,
+        header::IF_MODIFIED_SINCE,
+        header::IF_UNMODIFIED_SINCE,
+        header::IF_RANGE,
+        header::RANGE,
+    ] {
+        headers.remove(name);
+    }
+    upstream
+}
+
+/// Refreshes an expired entry in the background while it is served stale.
+///
+/// The stale entry is kept when the inner service fails, and dropped when the resource
+/// stopped being cacheable.
+fn revalidate<T>(
+    inner: T,
+    cache: ResponseCache,
+    config: Arc<CacheConfig>,
+    base: CacheKey,
+    req: Request<Body>,
+    guard: FlightGuard,
+) where
+    T: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    T::Future: Send + 'static,
+{
+    tokio::spawn(async move {
+        let req_headers = req.headers().clone();
+        let key = base
+            .clone()
+            .with_vary(&cache.vary(&base).await, &req_headers);
+        let response = match inner.oneshot(req).await {
+            Ok(response) if !response.status().is_server_error() => response,
+            _ => {
+                log::debug!("failed to refresh {key}, keeping the stale entry");
+                return;
+            }
+        };
+        match fill(
+            &cache,
+            &config,
+            &base,
+            &req_headers,
+            response,
+            Some(guard),
+            false,
+        )
+        .await
+        {
+            Filled::Stored(..) => {}
+            // nobody reads this body, drive it to the end so it gets stored
+            Filled::Streaming(response) => {
+                _ = to_bytes(response.into_body(), usize::MAX).await;
+            }
+            Filled::Passed(_) => {
+                cache.remove(&key).await;
+            }
+        }
+    });
+}
+
+/// What became of a response of the inner service.
+enum Filled {
+    /// The response is stored.
+    Stored(CacheKey, CachedResponse),
+    /// The response will be stored once its body went through.
+    Streaming(Response<Body>),
+    /// The response isn't cacheable.
+    Passed(Response<Body>),
+}
+
+/// Stores the response of the inner service if it matches the [`CacheConfig`].
+///
+/// Small bodies are buffered so the first response already benefits from validators and
+/// compression. Large bodies, or bodies of unknown length, are streamed to the client
+/// while being stored.
+///
+/// The `guard` of a coalesced miss is released once the entry is stored, or given up.
+/// A `private` request, carrying a session cookie, doesn't store the pages.
+///
+/// The response is stored for its `s-maxage` or `max-age`, the time-to-live of its rule
+/// otherwise, and never when it must be revalidated on each request.
+async fn fill(
+    cache: &ResponseCache,
+    config: &CacheConfig,
+    base: &CacheKey,
+    req_headers: &HeaderMap,
+    mut response: Response<Body>,
+    guard: Option<FlightGuard>,
+    private: bool,
+) -> Filled {
+    // partial or error responses would be replayed for every request
+    let origin = CacheControl::parse(response.headers());
+    if response.status() != StatusCode::OK || !is_shareable(response.headers(), origin.as_ref()) {
+        cache.pass(base);
+        return Filled::Passed(response);
+    }
+    let Some(vary) = key::vary_names(response.headers()) else {
+        cache.pass(base);
+        return Filled::Passed(response);
+    };
+    let key = base.clone().with_vary(&vary, req_headers);
+
+    // if matches the condition, continue
+    // else returns response
+    let path = base.path();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let Some(rule) = config.rule_for_route(path, content_type) else {
+        cache.pass(base);
+        return Filled::Passed(response);
+    };
+    if pages::is_page(response.headers()) {
+        // the next anonymous request may still store it
+        if private {
+            return Filled::Passed(response);
+        }
+        if config
+            .pages
+            .as_ref()
+            .is_some_and(|pages| pages.is_excluded(path))
+        {
+            cache.pass(base);
+            return Filled::Passed(response);
+        }
+    }
+
+    // the hashed URLs and the route policies always set their own `Cache-Control`
+    let hashed = config.fingerprints.logical(path).is_some();
+    let overridden = config.override_cache_control || hashed || config.routes.rule(path).is_some();
+    let expiry = match origin.as_ref().filter(|_| !overridden) {
+        Some(origin) => Expiry {
+            ttl: origin.ttl().or(rule.ttl),
+            ..config.expiry(rule)
+        },
+        None => config.expiry(rule),
+    };
+    if expiry.ttl == Some(Duration::ZERO) {
+        cache.pass(base);
+        return Filled::Passed(response);
+    }
+
+    let body_limit = cache
+        .max_entry_bytes()
+        .map_or(config.max_body_size, |max| max.min(config.max_body_size));
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+        .or_else(|| {
+            let size = response.body().size_hint().exact()?;
+            usize::try_from(size).ok()
+        });
+    let too_large = length.is_some_and(|length| length > body_limit);
+    if too_large {
+        log::debug!("{key} is too large to be cached");
+        cache.pass(base);
+        return Filled::Passed(response);
+    }
+
+    // `cached`
+
+    let headers = response.headers_mut();
+    if origin.is_none() || overridden {
+        // the content of a hashed URL never changes
+        let cache_control = match hashed {
+            true => HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL),
+            false => rule.cache_control.clone(),
+        };
+        headers.insert(header::CACHE_CONTROL, cache_control);
+    }
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if !config.encodings.is_empty() {
+        encoding::vary_on_accept_encoding(headers);
+    }
+
+    if length.is_none_or(|length| length > config.stream_size) {
+        let (parts, body) = response.into_parts();
+        let stored = parts.clone();
+        let cache = cache.clone();
+        let commit: Commit = Box::new(move |bytes| {
+            let mut parts = stored;
+            conditional::add_validators(&mut parts, &bytes);
+            // the store may be slow, don't hold the end of the body for it
+            tokio::spawn(async move {
+                // the waiting requests are released once the entry is stored
+                let _guard = guard;
+                if cache
+                    .insert_with(key.clone(), parts, bytes, expiry)
+                    .await
+                    .is_none()
+                {
+                    log::debug!("{key} is too large to be cached");
+                }
+            });
+        });
+        let body = Body::new(TeeBody::new(body, body_limit, length, commit));
+        return Filled::Streaming(Response::from_parts(parts, body));
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    match to_bytes(body, body_limit).await {
+        Ok(bytes) => {
+            conditional::add_validators(&mut parts, &bytes);
+            let stored = cache
+                .insert_with(key.clone(), parts.clone(), bytes.clone(), expiry)
+                .await;
+            let cached = stored.unwrap_or_else(|| {
+                log::debug!("{key} is too large to be cached");
+                CachedResponse {
+                    parts,
+                    body: bytes,
+                    stored_at: Instant::now(),
+                    expires_at: Some(Instant::now()),
+                }
+            });
+            Filled::Stored(key, cached)
+        }
+        Err(error) => {
+            log::error!("failed to read the body of {key}: {error}");
+            Filled::Passed(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to read the response body"))
+                    .unwrap_or_default(),
+            )
+        }
+    }
+}
+
+/// Builds and stores the `encoding` variant of a cached response.
+///
+/// The pre-compressed sibling file is used when there is one, otherwise the body is
+/// compressed on a blocking thread. Responses not worth compressing are served as is.
+async fn variant(
+    cache: &ResponseCache,
+    config: &CacheConfig,
+    key: &CacheKey,
+    path: &str,
+    identity: CachedResponse,
+    encoding: Encoding,
+) -> CachedResponse {
+    let compressible = identity
+        .parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| config.rule_for_route(path, value))
+        .is_some_and(|rule| rule.compress)
+        && !identity
+            .parts
+            .headers
+            .contains_key(header::CONTENT_ENCODING)
+        && identity.body.len() >= config.compress_min_size;
+    if !compressible {
+        return identity;
+    }
+
+    let sibling = match config.precompressed_root.as_deref() {
+        Some(root) => encoding::read_sibling(root, path, encoding).await,
+        None => None,
+    };
+    let compressed = match sibling {
+        Some(body) => Some(body),
+        None => {
+            let raw = identity.body.clone();
+            tokio::task::spawn_blocking(move || encoding::compress(encoding, &raw))
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .map(Bytes::from)
+        }
+    };
+
+    let mut parts = identity.parts.clone();
+    let body = match compressed {
+        Some(body) if body.len() < identity.body.len() => {
+            let headers = &mut parts.headers;
+            headers.insert(header::CONTENT_ENCODING, encoding.header_value());
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+            // each representation needs its own strong validator
+            headers.insert(header::ETAG, conditional::etag(&body));
+            body
+        }
+        // compression doesn't pay, remember it by storing the raw body as the variant
+        Some(_) => identity.body.clone(),
+        None => {
+            log::debug!("failed to compress {key} with {encoding}");
+            return identity;
+        }
+    };
+
+    // the variant expires with the response it derives from
+    let expiry = Expiry {
+        ttl: identity.ttl_left(),
+        grace: config.grace(),
+    };
+    cache
+        .insert_with(
+            key.with_encoding(encoding),
+            parts.clone(),
+            body.clone(),
+            expiry,
+        )
+        .await;
+    CachedResponse {
+        parts,
+        body,
+        ..identity
+    }
+}
+
+/// Whether a response may be stored in a cache shared by all clients.
+///
+/// Cookies and responses marked `private` or `no-store` belong to a single client.
+fn is_shareable(headers: &HeaderMap, directives: Option<&CacheControl>) -> bool {
+    directives.is_none_or(CacheControl::is_shareable) && !headers.contains_key(header::SET_COOKIE)
+}
+
+/// Answers a request from a cached response, honoring its preconditions and ranges.
+/// The body is left out for `HEAD` requests.
+fn serve(
+    req_headers: &HeaderMap,
+    cached: CachedResponse,
+    head: bool,
+    status: CacheStatus,
+) -> (Response<Body>, CacheStatus) {
+    let age = cached.stored_at.elapsed().as_secs();
+    let served_type = cached.parts.headers.get(header::CONTENT_TYPE).cloned();
+    let mut response = if conditional::is_not_modified(req_headers, &cached.parts.headers) {
+        conditional::not_modified(cached.parts)
+    } else {
+        range::respond(req_headers, cached)
+    };
+    if head {
+        *response.body_mut() = Body::empty();
+    }
+    if status.is_cached() {
+        response
+            .headers_mut()
+            .insert(header::AGE, HeaderValue::from(age));
+    }
+    if let Some(served_type) = served_type {
+        response.extensions_mut().insert(ServedType(served_type));
+    }
+    (response, status)
+}
+
+/// The `Content-Type` of the cached response, kept for the metrics when the answer
+/// doesn't carry it, like a `304 Not Modified`.
+#[derive(Clone, Debug)]
+struct ServedType(HeaderValue);
+
+/// The content type of a response, the one of its cached entry if it is cached.
+pub(super) fn content_type(response: &Response<Body>) -> Option<&str> {
+    response
+        .extensions()
+        .get::<ServedType>()
+        .map(|served| &served.0)
+        .or_else(|| response.headers().get(header::CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::testing::{get, Answer, Origin};
+    use crate::cache::{PageConfig, DEFAULT_STATUS_HEADER};
+
+    const TTL: Duration = Duration::from_millis(50);
+
+    fn service(origin: &Origin, config: CacheConfig) -> ResponseCacheService<Origin> {
+        let config = config
+            .content_type("text/css", HeaderValue::from_static("public, max-age=300"))
+            .ttl("text/css", TTL);
+        ResponseCacheLayer::with_config(ResponseCache::builder().build(), config)
+            .layer(origin.clone())
+    }
+
+    async fn expire() {
+        tokio::time::sleep(TTL + Duration::from_millis(30)).await;
+    }
+
+    #[tokio::test]
+    async fn times_the_whole_response() {
+        let origin = Origin::new().delay(Duration::from_millis(20));
+        let service = service(&origin, CacheConfig::new().server_timing(true));
+
+        let request = || Request::get("/app.css").body(Body::empty()).unwrap();
+        let timing = |response: &Response<Body>| {
+            let value = response.headers()[SERVER_TIMING].to_str().unwrap();
+            let (status, total) = value.split_once(", ").unwrap();
+            let millis: f64 = total.strip_prefix("total;dur=").unwrap().parse().unwrap();
+            (status.to_owned(), millis)
+        };
+        let response = service.clone().oneshot(request()).await.unwrap();
+        let (status, millis) = timing(&response);
+        assert_eq!(status, r#"cache;desc="MISS""#);
+        // the origin is part of the time of a miss
+        assert!(millis >= 20.0, "{millis}");
+        let response = service.clone().oneshot(request()).await.unwrap();
+        assert_eq!(timing(&response).0, r#"cache;desc="HIT""#);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_encoded_pages_from_the_sessions() {
+        let origin = Origin::new();
+        let cache = ResponseCache::builder().build();
+        let config = CacheConfig::new().pages(PageConfig::default());
+        let service = ResponseCacheLayer::with_config(cache.clone(), config).layer(origin.clone());
+
+        // the identity entry of the page is gone, its brotli variant is left
+        let key = CacheKey::new(&Method::GET, &Uri::from_static("/")).unwrap();
+        let (parts, ()) = Response::builder()
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CONTENT_ENCODING, "br")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let body = Bytes::from_static(b"anonymous page");
+        cache
+            .insert_with(
+                key.with_encoding(Encoding::Brotli),
+                parts,
+                body,
+                Expiry::default(),
+            )
+            .await
+            .unwrap();
+
+        let request = Request::get("/")
+            .header(header::ACCEPT_ENCODING, "br")
+            .header(header::COOKIE, "id=s3cret")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[DEFAULT_STATUS_HEADER], "BYPASS");
+        assert_eq!(origin.calls(), 1);
+
+        let request = Request::get("/")
+            .header(header::ACCEPT_ENCODING, "br")
+            .body(Body::empty())
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[DEFAULT_STATUS_HEADER], "HIT");
+    }
+
+    #[tokio::test]
+    async fn expires_after_the_ttl() {
+        let origin = Origin::new();
+        let service = service(&origin, CacheConfig::new());
+
+        assert_eq!(get(&service, "/app.css").await.unwrap().0, "MISS");
+        assert_eq!(get(&service, "/app.css").await.unwrap().0, "HIT");
+        expire().await;
+        let (status, body) = get(&service, "/app.css").await.unwrap();
+        assert_eq!((status.as_str(), body.as_str()), ("EXPIRED", "call 2"));
+        assert_eq!(get(&service, "/app.css").await.unwrap().0, "HIT");
+        assert_eq!(origin.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn refreshes_stale_entries_in_the_background() {
+        let origin = Origin::new();
+        let config = CacheConfig::new().stale_while_revalidate(Duration::from_secs(60));
+        let service = service(&origin, config);
+
+        get(&service, "/app.css").await.unwrap();
+        expire().await;
+        let (status, body) = get(&service, "/app.css").await.unwrap();
+        assert_eq!((status.as_str(), body.as_str()), ("STALE", "call 1"));
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(origin.calls(), 2);
+        let (status, body) = get(&service, "/app.css").await.unwrap();
+        assert_eq!((status.as_str(), body.as_str()), ("HIT", "call 2"));
+    }
+
+    #[tokio::test]
+    async fn serves_stale_entries_when_the_inner_service_fails() {
+        let origin = Origin::new();
+        let config = CacheConfig::new().stale_if_error(Duration::from_secs(60));
+        let service = service(&origin, config);
+
+        get(&service, "/app.css").await.unwrap();
+        expire().await;
+        for answer in [Answer::ServerError, Answer::Error] {
+            origin.answer(answer);
+            let (status, body) = get(&service, "/app.css").await.unwrap();
+            assert_eq!((status.as_str(), body.as_str()), ("STALE", "call 1"));
+        }
+
+        origin.answer(Answer::Ok);
+        let (status, body) = get(&service, "/app.css").await.unwrap();
+        assert_eq!((status.as_str(), body.as_str()), ("EXPIRED", "call 4"));
+    }
+
+    #[tokio::test]
+    async fn forwards_the_failures_past_the_stale_if_error_window() {
+        let origin = Origin::new();
+        let config = CacheConfig::new().stale_if_error(Duration::from_millis(10));
+        let service = service(&origin, config);
+
+        get(&service, "/app.css").await.unwrap();
+        expire().await;
+        origin.answer(Answer::ServerError);
+        let request = Request::get("/app.css").body(Body::empty()).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        origin.answer(Answer::Error);
+        assert!(get(&service, "/app.css").await.is_err());
+        assert_eq!(origin.calls(), 3);
+    }
+}
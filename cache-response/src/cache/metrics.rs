@@ -0,0 +1,236 @@
+use super::{CacheStats, CacheStatus, ContentTypeStats, ResponseCache};
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Response};
+use std::fmt::Write;
+
+/// Default path of the metrics endpoint.
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// `Content-Type` of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Answers a scrape with the cache metrics.
+pub(super) async fn handle(cache: &ResponseCache) -> Response<Body> {
+    let stats = cache.stats().await;
+    let body = render(&stats, cache.capacity(), &cache.content_type_stats());
+    let mut response = Response::new(Body::from(body));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    response
+}
+
+/// Writes the metrics in the Prometheus text exposition format.
+///
+/// ```text
+/// # TYPE response_cache_requests_total counter
+/// response_cache_requests_total{status="hit"} 42
+/// # TYPE response_cache_content_type_hit_ratio gauge
+/// response_cache_content_type_hit_ratio{content_type="text/css"} 0.875
+/// ```
+pub fn render(
+    stats: &CacheStats,
+    capacity: usize,
+    content_types: &[(String, ContentTypeStats)],
+) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        _ = writeln!(out, "# HELP response_cache_{name} {help}");
+        _ = writeln!(out, "# TYPE response_cache_{name} {kind}");
+        for (labels, value) in samples {
+            _ = writeln!(out, "response_cache_{name}{labels} {value}");
+        }
+    };
+    let single = |value: String| [(String::new(), value)];
+
+    let requests: Vec<_> = CacheStatus::ALL
+        .iter()
+        .map(|&status| {
+            let count = match status {
+                CacheStatus::Hit => stats.hits,
+                CacheStatus::Miss => stats.misses,
+                CacheStatus::Bypass => stats.bypasses,
+                CacheStatus::Stale => stats.stale,
+                CacheStatus::Expired => stats.expired,
+            };
+            (status_label(status), count.to_string())
+        })
+        .collect();
+    metric(
+        "requests_total",
+        "counter",
+        "Requests handled by the cache, by status.",
+        &requests,
+    );
+    metric(
+        "coalesced_total",
+        "counter",
+        "Misses answered by waiting for a concurrent request.",
+        &single(stats.coalesced.to_string()),
+    );
+    metric(
+        "evictions_total",
+        "counter",
+        "Entries dropped to respect the entry limit or the byte budget.",
+        &single(stats.evictions.to_string()),
+    );
+    metric(
+        "entries",
+        "gauge",
+        "Entries currently stored.",
+        &single(stats.entries.to_string()),
+    );
+    metric(
+        "bytes",
+        "gauge",
+        "Weight of the stored entries, bodies plus headers.",
+        &single(stats.bytes.to_string()),
+    );
+    metric(
+        "capacity_entries",
+        "gauge",
+        "Maximum number of entries.",
+        &single(capacity.to_string()),
+    );
+
+    let by_type: Vec<_> = content_types
+        .iter()
+        .flat_map(|(content_type, stats)| {
+            CacheStatus::ALL.iter().map(move |&status| {
+                let labels = format!(
+                    "{{content_type=\"{}\",status=\"{}\"}}",
+                    escape(content_type),
+                    status.as_str().to_ascii_lowercase()
+                );
+                (labels, stats.count(status).to_string())
+            })
+        })
+        .collect();
+    metric(
+        "content_type_requests_total",
+        "counter",
+        "Requests handled by the cache, by response content type and status.",
+        &by_type,
+    );
+    let ratios: Vec<_> = content_types
+        .iter()
+        .map(|(content_type, stats)| {
+            let labels = format!("{{content_type=\"{}\"}}", escape(content_type));
+            (labels, stats.hit_ratio().to_string())
+        })
+        .collect();
+    metric(
+        "content_type_hit_ratio",
+        "gauge",
+        "Share of the requests served from the cache, by response content type.",
+        &ratios,
+    );
+    out
+}
+
+fn status_label(status: CacheStatus) -> String {
+    format!("{{status=\"{}\"}}", status.as_str().to_ascii_lowercase())
+}
+
+/// Escapes a label value, see the exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheConfig, ResponseCacheLayer};
+    use axum::body::to_bytes;
+    use axum::http::Request;
+    use axum::response::Html;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::{Layer, ServiceExt};
+
+    async fn get_body<S>(service: &S, uri: &str) -> String
+    where
+        S: tower::Service<Request<Body>, Response = Response<Body>> + Clone,
+        S::Error: std::fmt::Debug,
+    {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn sample<'a>(metrics: &'a str, name: &str) -> &'a str {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{name} is missing from\n{metrics}"))
+    }
+
+    #[tokio::test]
+    async fn scrapes_the_counters() {
+        let css = (
+            [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+            "body {}",
+        );
+        let router = Router::new()
+            .route("/app.css", get(move || async move { css }))
+            .route("/", get(|| async { Html("<p>home</p>") }));
+        let cache = ResponseCache::default();
+        let config = CacheConfig::default().metrics(DEFAULT_METRICS_PATH);
+        let service = ResponseCacheLayer::with_config(cache, config).layer(router);
+
+        for uri in ["/app.css", "/app.css", "/app.css", "/app.css", "/"] {
+            get_body(&service, uri).await;
+        }
+        let metrics = get_body(&service, DEFAULT_METRICS_PATH).await;
+
+        assert_eq!(
+            sample(&metrics, r#"response_cache_requests_total{status="hit"}"#),
+            "3"
+        );
+        assert_eq!(
+            sample(&metrics, r#"response_cache_requests_total{status="miss"}"#),
+            "1"
+        );
+        assert_eq!(
+            sample(
+                &metrics,
+                r#"response_cache_requests_total{status="bypass"}"#
+            ),
+            "1"
+        );
+        assert_eq!(sample(&metrics, "response_cache_evictions_total"), "0");
+        assert_eq!(sample(&metrics, "response_cache_entries"), "1");
+        let bytes: usize = sample(&metrics, "response_cache_bytes").parse().unwrap();
+        assert!(bytes > "body {}".len());
+        assert_eq!(
+            sample(
+                &metrics,
+                r#"response_cache_content_type_requests_total{content_type="text/css",status="hit"}"#
+            ),
+            "3"
+        );
+        assert_eq!(
+            sample(
+                &metrics,
+                r#"response_cache_content_type_hit_ratio{content_type="text/css"}"#
+            ),
+            "0.75"
+        );
+        assert_eq!(
+            sample(
+                &metrics,
+                r#"response_cache_content_type_hit_ratio{content_type="text/html"}"#
+            ),
+            "0"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+/// A `type/subtype` pattern matched against the `Content-Type` of the responses,
+/// `*` standing for any subtype (`image/*`) or any type (`*/*`).
+///
+/// The parameters of a `Content-Type` are ignored and the comparison is
+/// case-insensitive, `text/javascript` matches `Text/JavaScript; charset=utf-8`
+/// but not `text/javascript-template`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MediaRange {
+    type_: String,
+    subtype: String,
+}
+
+impl MediaRange {
+    /// Matches every media type.
+    pub fn any() -> Self {
+        Self {
+            type_: "*".to_owned(),
+            subtype: "*".to_owned(),
+        }
+    }
+
+    /// Parses a pattern like `text/css`, `image/*` or `*/*`, `None` when it isn't
+    /// one. Parameters are dropped.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let (type_, subtype) = split(pattern)?;
+        let valid = match (type_.as_str(), subtype.as_str()) {
+            ("*", subtype) => subtype == "*",
+            (type_, subtype) => is_token(type_) && (subtype == "*" || is_token(subtype)),
+        };
+        valid.then_some(Self { type_, subtype })
+    }
+
+    /// Whether a `Content-Type` value matches the pattern.
+    pub fn matches(&self, content_type: &str) -> bool {
+        let Some((type_, subtype)) = split(content_type) else {
+            return false;
+        };
+        (self.type_ == "*" || self.type_ == type_)
+            && (self.subtype == "*" || self.subtype == subtype)
+    }
+}
+
+impl fmt::Display for MediaRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)
+    }
+}
+
+/// The `type/subtype` of a `Content-Type` value, lowercase and without parameters.
+pub fn essence(content_type: &str) -> Option<String> {
+    let (type_, subtype) = split(content_type)?;
+    (is_token(&type_) && is_token(&subtype)).then(|| format!("{type_}/{subtype}"))
+}
+
+/// The lowercase type and subtype of a media type, its parameters dropped.
+fn split(value: &str) -> Option<(String, String)> {
+    let essence = value.split(';').next()?.trim();
+    let (type_, subtype) = essence.split_once('/')?;
+    Some((type_.to_ascii_lowercase(), subtype.to_ascii_lowercase()))
+}
+
+/// Whether `value` is a token of [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-5.6.2),
+/// the wildcard `*` left aside.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value != "*"
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// The lowercase extension of the last segment of `path`, `woff2` for
+/// `/pkg/font.woff2`.
+pub fn extension(path: &str) -> Option<String> {
+    let file = path.rsplit('/').next()?;
+    let (name, extension) = file.rsplit_once('.')?;
+    (!name.is_empty() && !extension.is_empty()).then(|| extension.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(pattern: &str) -> MediaRange {
+        MediaRange::parse(pattern).unwrap()
+    }
+
+    #[test]
+    fn ignores_the_parameters() {
+        let javascript = range("text/javascript");
+        assert!(javascript.matches("text/javascript"));
+        assert!(javascript.matches("text/javascript; charset=utf-8"));
+        assert!(javascript.matches(" Text/JavaScript ;charset=UTF-8"));
+        assert_eq!(
+            essence("text/javascript; charset=utf-8").as_deref(),
+            Some("text/javascript")
+        );
+    }
+
+    #[test]
+    fn matches_whole_types_only() {
+        let css = range("text/css");
+        assert!(!css.matches("application/x-not-really-css-thing"));
+        assert!(!css.matches("text/css-template"));
+        assert!(!css.matches("text"));
+        assert!(!css.matches(""));
+        assert!(range("image/svg+xml").matches("image/svg+xml"));
+        assert!(!range("image/svg+xml").matches("image/svg"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        let images = range("image/*");
+        assert!(images.matches("image/webp"));
+        assert!(images.matches("image/svg+xml; charset=utf-8"));
+        assert!(!images.matches("font/woff2"));
+        assert!(MediaRange::any().matches("application/json"));
+        assert_eq!(range("*/*"), MediaRange::any());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in ["css", "*/css", "text/", "/css", "text/c ss", "te xt/*", "*"] {
+            assert_eq!(MediaRange::parse(pattern), None, "{pattern}");
+        }
+        assert_eq!(range("Font/WOFF2; q=1").to_string(), "font/woff2");
+    }
+
+    #[test]
+    fn finds_the_extension() {
+        assert_eq!(extension("/pkg/font.WOFF2").as_deref(), Some("woff2"));
+        assert_eq!(
+            extension("/pkg/app.0123456789abcdef.css").as_deref(),
+            Some("css")
+        );
+        assert_eq!(extension("/pkg.d/app"), None);
+        assert_eq!(extension("/.env"), None);
+        assert_eq!(extension("/file."), None);
+    }
+}
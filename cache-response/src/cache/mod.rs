@@ -0,0 +1,51 @@
+//! Caching of responses for static assets (css, javascript, wasm, ...).
+//!
+//! A [`ResponseCache`] holds the stored responses and a [`ResponseCacheLayer`]
+//! plugs it into any axum or leptos router, the [`CacheConfig`] deciding which
+//! responses are cached and how they are served.
+
+pub mod admin;
+pub mod backend;
+mod body;
+pub mod coalesce;
+pub mod conditional;
+mod config;
+pub mod directives;
+pub mod encoding;
+pub mod key;
+mod layer;
+pub mod metrics;
+pub mod mime;
+pub mod pages;
+pub mod policy;
+pub mod range;
+pub mod status;
+mod store;
+#[cfg(test)]
+mod testing;
+pub mod warm;
+pub mod watch;
+
+pub use admin::{AdminConfig, DEFAULT_ADMIN_HEADER, DEFAULT_ADMIN_PREFIX};
+pub use backend::{
+    CacheStore, FileStore, MemoryStore, StoreFuture, StoreLimits, StoreStats, DEFAULT_MAX_ENTRIES,
+};
+pub use config::{
+    CacheConfig, CacheRule, RulePattern, DEFAULT_CACHE_CONTROL, DEFAULT_COALESCE_TIMEOUT,
+    DEFAULT_COMPRESS_MIN_SIZE, DEFAULT_MAX_BODY_SIZE, DEFAULT_STATUS_HEADER, DEFAULT_STREAM_SIZE,
+    IMMUTABLE_CACHE_CONTROL,
+};
+pub use directives::CacheControl;
+pub use encoding::Encoding;
+pub use key::CacheKey;
+pub use layer::{ResponseCacheLayer, ResponseCacheService};
+pub use metrics::DEFAULT_METRICS_PATH;
+pub use pages::{
+    PageConfig, DEFAULT_PAGE_CACHE_CONTROL, DEFAULT_PAGE_TTL, DEFAULT_SESSION_COOKIES,
+};
+pub use policy::{CachePolicy, RoutePolicies};
+pub use status::CacheStatus;
+pub use store::{
+    CacheStats, CachedResponse, ContentTypeStats, EntryInfo, EvictionPolicy, Expiry, Lookup,
+    ResponseCache, ResponseCacheBuilder,
+};
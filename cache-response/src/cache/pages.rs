@@ -0,0 +1,115 @@
+use super::mime::{self, MediaRange};
+use super::{CacheRule, RulePattern};
+use axum::http::{header, HeaderMap, HeaderValue};
+use std::time::Duration;
+
+/// Default time-to-live of a rendered page.
+pub const DEFAULT_PAGE_TTL: Duration = Duration::from_secs(60);
+
+/// Default `Cache-Control` of a rendered page, browsers revalidate it on each visit.
+pub const DEFAULT_PAGE_CACHE_CONTROL: &str = "no-cache";
+
+/// Default cookies telling a request belongs to a session, `id` being the cookie of
+/// `tower-sessions`.
+pub const DEFAULT_SESSION_COOKIES: [&str; 2] = ["id", "session"];
+
+/// Configuration of the page cache, disabled unless set with
+/// [`CacheConfig::pages`](super::CacheConfig::pages).
+///
+/// The `text/html` responses, like the ones rendered by `leptos_routes`, are cached by
+/// path and query for anonymous visitors :
+///
+/// - a request carrying a session cookie is never served a cached page, nor stores one,
+/// - the routes under an [`exclude`](Self::exclude)d path are never cached, a route can
+///   also opt out at runtime with a `Cache-Control: no-store` or `private` response,
+/// - a server function drops the stale renders with
+///   [`ResponseCache::invalidate`](super::ResponseCache::invalidate).
+///
+/// ```ignore
+/// let config = CacheConfig::default().pages(
+///     PageConfig::default()
+///         .ttl(Duration::from_secs(300))
+///         .session_cookie("auth")
+///         .exclude("/account"),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct PageConfig {
+    rule: CacheRule,
+    session_cookies: Vec<String>,
+    excluded: Vec<String>,
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        Self {
+            rule: CacheRule {
+                pattern: RulePattern::ContentType(
+                    MediaRange::parse("text/html").expect("text/html is a media type"),
+                ),
+                cache_control: HeaderValue::from_static(DEFAULT_PAGE_CACHE_CONTROL),
+                compress: true,
+                ttl: Some(DEFAULT_PAGE_TTL),
+            },
+            session_cookies: DEFAULT_SESSION_COOKIES.map(str::to_owned).to_vec(),
+            excluded: Vec::new(),
+        }
+    }
+}
+
+impl PageConfig {
+    /// How long a rendered page is served from the cache, defaults to [`DEFAULT_PAGE_TTL`].
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.rule.ttl = Some(ttl);
+        self
+    }
+
+    /// The `Cache-Control` sent to clients, defaults to [`DEFAULT_PAGE_CACHE_CONTROL`].
+    pub fn cache_control(mut self, cache_control: HeaderValue) -> Self {
+        self.rule.cache_control = cache_control;
+        self
+    }
+
+    /// Adds a cookie telling a request belongs to a session.
+    pub fn session_cookie(mut self, name: impl Into<String>) -> Self {
+        self.session_cookies.push(name.into());
+        self
+    }
+
+    /// Never caches the page at `path`, nor the ones below it.
+    pub fn exclude(mut self, path: impl Into<String>) -> Self {
+        self.excluded
+            .push(path.into().trim_end_matches('/').to_owned());
+        self
+    }
+
+    pub fn rule(&self) -> &CacheRule {
+        &self.rule
+    }
+
+    /// Whether the request carries one of the session cookies.
+    pub fn has_session(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.split_once('=').map(|(name, _)| name.trim()))
+            .any(|name| self.session_cookies.iter().any(|session| session == name))
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.excluded.iter().any(|excluded| {
+            path.strip_prefix(excluded.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+/// Whether a response is a rendered page.
+pub fn is_page(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| mime::essence(value.to_str().ok()?))
+        .is_some_and(|essence| essence == "text/html")
+}
@@ -0,0 +1,288 @@
+use super::mime::MediaRange;
+use super::{CacheRule, RulePattern};
+use axum::http::HeaderValue;
+use std::time::Duration;
+
+/// How the responses of a route are cached, by the middleware and by the clients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Never stored, by the middleware nor by the clients.
+    NoStore,
+    /// Only stored by the client, never by the middleware.
+    Private { max_age: Duration },
+    /// Stored by the middleware for `s_maxage`, or `max_age` when unset.
+    Public {
+        max_age: Duration,
+        s_maxage: Option<Duration>,
+        immutable: bool,
+    },
+}
+
+impl CachePolicy {
+    pub fn no_store() -> Self {
+        Self::NoStore
+    }
+
+    pub fn private(max_age: Duration) -> Self {
+        Self::Private { max_age }
+    }
+
+    pub fn public(max_age: Duration) -> Self {
+        Self::Public {
+            max_age,
+            s_maxage: None,
+            immutable: false,
+        }
+    }
+
+    /// How long the middleware keeps a [`Public`](Self::Public) response,
+    /// ignored by the other policies.
+    pub fn s_maxage(mut self, ttl: Duration) -> Self {
+        if let Self::Public { s_maxage, .. } = &mut self {
+            *s_maxage = Some(ttl);
+        }
+        self
+    }
+
+    /// Tells the clients a [`Public`](Self::Public) response never changes,
+    /// ignored by the other policies.
+    pub fn immutable(mut self) -> Self {
+        if let Self::Public { immutable, .. } = &mut self {
+            *immutable = true;
+        }
+        self
+    }
+
+    /// Whether the middleware may store the responses.
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Self::Public { .. })
+    }
+
+    /// The `Cache-Control` sent with the responses.
+    pub fn header_value(&self) -> HeaderValue {
+        let value = match self {
+            Self::NoStore => "no-store".to_owned(),
+            Self::Private { max_age } => format!("private, max-age={}", max_age.as_secs()),
+            Self::Public {
+                max_age,
+                s_maxage,
+                immutable,
+            } => {
+                let mut value = format!("public, max-age={}", max_age.as_secs());
+                if let Some(s_maxage) = s_maxage {
+                    value.push_str(&format!(", s-maxage={}", s_maxage.as_secs()));
+                }
+                if *immutable {
+                    value.push_str(", immutable");
+                }
+                value
+            }
+        };
+        HeaderValue::from_str(&value).expect("cache-control is ascii")
+    }
+
+    fn rule(&self) -> Option<CacheRule> {
+        let Self::Public {
+            max_age, s_maxage, ..
+        } = self
+        else {
+            return None;
+        };
+        Some(CacheRule {
+            pattern: RulePattern::ContentType(MediaRange::any()),
+            cache_control: self.header_value(),
+            compress: true,
+            ttl: Some(s_maxage.unwrap_or(*max_age)),
+        })
+    }
+}
+
+/// The [`CachePolicy`] of each route, consulted by the middleware before the
+/// content type rules. Declared next to the routes :
+///
+/// ```ignore
+/// pub fn route_policies() -> RoutePolicies {
+///     RoutePolicies::default()
+///         .route("/", CachePolicy::public(Duration::ZERO).s_maxage(Duration::from_secs(60)))
+///         .route("/post/:id", CachePolicy::public(Duration::from_secs(300)))
+///         .route("/account/*any", CachePolicy::no_store())
+/// }
+/// ```
+///
+/// Paths use the axum syntax of `generate_route_list`, `:name` matching a segment
+/// and `*name` the rest of the path, which can't be empty. The first matching route
+/// wins.
+#[derive(Clone, Debug, Default)]
+pub struct RoutePolicies {
+    routes: Vec<Route>,
+}
+
+#[derive(Clone, Debug)]
+struct Route {
+    path: String,
+    policy: CachePolicy,
+    /// The rule of a [`CachePolicy::Public`] route.
+    rule: Option<CacheRule>,
+}
+
+impl RoutePolicies {
+    pub fn route(mut self, path: impl Into<String>, policy: CachePolicy) -> Self {
+        self.routes.push(Route {
+            path: path.into(),
+            rule: policy.rule(),
+            policy,
+        });
+        self
+    }
+
+    /// Warns about the policies of routes missing from `paths`, like the ones of
+    /// `generate_route_list`, they are likely a typo.
+    pub fn check<'a>(self, paths: impl IntoIterator<Item = &'a str>) -> Self {
+        for path in self.unknown(paths) {
+            log::warn!("the cache policy of {path} matches no route");
+        }
+        self
+    }
+
+    /// The routes with a policy missing from `paths`.
+    fn unknown<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Vec<&str> {
+        let paths: Vec<&str> = paths.into_iter().collect();
+        self.routes
+            .iter()
+            .map(|route| route.path.as_str())
+            .filter(|path| !paths.contains(path))
+            .collect()
+    }
+
+    pub fn policy(&self, path: &str) -> Option<&CachePolicy> {
+        self.find(path).map(|route| &route.policy)
+    }
+
+    /// The rule of a path whose policy is [`CachePolicy::Public`].
+    pub fn rule(&self, path: &str) -> Option<&CacheRule> {
+        self.find(path).and_then(|route| route.rule.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    fn find(&self, path: &str) -> Option<&Route> {
+        self.routes.iter().find(|route| matches(&route.path, path))
+    }
+}
+
+/// Whether `path` matches the route `pattern`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut segments = path.trim_matches('/').split('/');
+    for expected in pattern.trim_matches('/').split('/') {
+        // like axum, a wildcard doesn't match an empty rest
+        if expected.starts_with('*') {
+            return segments.next().is_some_and(|segment| !segment.is_empty());
+        }
+        match segments.next() {
+            Some(segment) if expected.starts_with(':') => {
+                if segment.is_empty() {
+                    return false;
+                }
+            }
+            Some(segment) if segment == expected => {}
+            _ => return false,
+        }
+    }
+    segments.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+
+    fn policies() -> RoutePolicies {
+        RoutePolicies::default()
+            .route(
+                "/",
+                CachePolicy::public(Duration::ZERO).s_maxage(Duration::from_secs(60)),
+            )
+            .route("/post/new", CachePolicy::no_store())
+            .route("/post/:id", CachePolicy::public(Duration::from_secs(300)))
+            .route(
+                "/account/*any",
+                CachePolicy::private(Duration::from_secs(60)),
+            )
+            .route(
+                "/pkg/*file",
+                CachePolicy::public(Duration::from_secs(60)).immutable(),
+            )
+    }
+
+    #[test]
+    fn matches_the_params_and_wildcards() {
+        let policies = policies();
+        let max_age = |path| match policies.policy(path) {
+            Some(CachePolicy::Public { max_age, .. }) => Some(max_age.as_secs()),
+            _ => None,
+        };
+
+        assert_eq!(max_age("/"), Some(0));
+        assert_eq!(max_age("/post/42"), Some(300));
+        assert_eq!(max_age("/post/42/"), Some(300));
+        assert_eq!(policies.policy("/post/42/comments"), None);
+        assert_eq!(policies.policy("/post"), None);
+        assert_eq!(policies.policy("/post//"), None);
+
+        let private = Some(&CachePolicy::private(Duration::from_secs(60)));
+        assert_eq!(policies.policy("/account/settings"), private);
+        assert_eq!(policies.policy("/account/settings/email"), private);
+        assert_eq!(policies.policy("/account"), None);
+        assert_eq!(policies.policy("/accounts/settings"), None);
+    }
+
+    #[test]
+    fn takes_the_first_matching_route() {
+        let policies = policies();
+        assert_eq!(policies.policy("/post/new"), Some(&CachePolicy::NoStore));
+        assert!(policies.rule("/post/new").is_none());
+        assert!(policies.rule("/post/42").is_some());
+
+        let reversed = RoutePolicies::default()
+            .route("/post/:id", CachePolicy::public(Duration::from_secs(300)))
+            .route("/post/new", CachePolicy::no_store());
+        assert!(reversed
+            .policy("/post/new")
+            .is_some_and(CachePolicy::is_shared));
+    }
+
+    #[test]
+    fn takes_precedence_over_the_content_types() {
+        let config = CacheConfig::default().routes(policies());
+
+        let rule = config
+            .rule_for_route("/pkg/app.js", "text/javascript")
+            .unwrap();
+        assert_eq!(rule.cache_control, "public, max-age=60, immutable");
+        assert_eq!(rule.ttl, Some(Duration::from_secs(60)));
+        // a public route caches any content type
+        let rule = config.rule_for_route("/post/42", "text/html").unwrap();
+        assert_eq!(rule.cache_control, "public, max-age=300");
+        let rule = config.rule_for_route("/", "text/html").unwrap();
+        assert_eq!(rule.ttl, Some(Duration::from_secs(60)));
+
+        // the other routes leave their assets to the content type rules
+        let rule = config.rule_for_route("/app.js", "text/javascript").unwrap();
+        assert_eq!(rule.cache_control, "public, max-age=300");
+        assert!(config.rule_for_route("/account/me", "text/html").is_none());
+    }
+
+    #[test]
+    fn checks_the_routes() {
+        let policies = policies();
+        let routes = ["/", "/post/:id", "/account/*any", "/pkg/*file"];
+        assert_eq!(policies.unknown(routes), ["/post/new"]);
+        assert!(policies
+            .unknown(["/", "/post/new", "/post/:id", "/account/*any", "/pkg/*file"])
+            .is_empty());
+        // the paths are compared as declared, not matched
+        assert_eq!(policies.unknown(["/post/42"]).len(), 5);
+    }
+}
@@ -0,0 +1,325 @@
+use super::CachedResponse;
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// More ranges than this in one request are ignored and the full body is sent.
+const MAX_RANGES: usize = 16;
+
+/// What a `Range` header asks for, given the length of the body.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRanges {
+    /// No usable `Range` header, the full body is sent.
+    Full,
+    /// The satisfiable ranges, sorted and with the overlapping or adjacent ones merged.
+    Partial(Vec<RangeInclusive<u64>>),
+    /// None of the requested ranges overlaps the body.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header value against a body of `len` bytes.
+///
+/// Invalid headers are ignored as allowed by
+/// [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-14.2).
+pub fn parse(range: &str, len: u64) -> ByteRanges {
+    let Some((unit, specs)) = range.split_once('=') else {
+        return ByteRanges::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return ByteRanges::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRanges::Full;
+        };
+        let range = match (first.parse::<u64>(), last) {
+            // `-500` : the last 500 bytes
+            (Err(_), suffix) if first.is_empty() => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(suffix) if len > 0 => Some(len.saturating_sub(suffix)..=len - 1),
+                Ok(_) => None,
+                Err(_) => return ByteRanges::Full,
+            },
+            // `500-` : from the byte 500 to the end
+            (Ok(first), "") => (first < len).then(|| first..=len - 1),
+            // `500-999`
+            (Ok(first), last) => match last.parse::<u64>() {
+                Ok(last) if last < first => return ByteRanges::Full,
+                Ok(last) => (first < len).then(|| first..=last.min(len - 1)),
+                Err(_) => return ByteRanges::Full,
+            },
+            (Err(_), _) => return ByteRanges::Full,
+        };
+        ranges.extend(range);
+    }
+
+    match ranges.len() {
+        0 => ByteRanges::Unsatisfiable,
+        n if n > MAX_RANGES => ByteRanges::Full,
+        _ => ByteRanges::Partial(merge(ranges)),
+    }
+}
+
+/// Sorts the ranges and merges those that overlap or touch, so a body is never sent
+/// more than once in a multipart response.
+fn merge(mut ranges: Vec<RangeInclusive<u64>>) -> Vec<RangeInclusive<u64>> {
+    ranges.sort_by_key(|range| *range.start());
+    let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=*range.end().max(last.end());
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Whether an `If-Range` precondition still matches the cached response.
+///
+/// Only strong entity tags and exact dates compare equal, anything else means
+/// the client's partial copy is outdated and the full body must be sent.
+fn if_range_matches(request: &HeaderMap, cached: &HeaderMap) -> bool {
+    let Some(if_range) = request.get(header::IF_RANGE) else {
+        return true;
+    };
+    if if_range.as_bytes().starts_with(b"W/") {
+        return false;
+    }
+    let validator = if if_range.as_bytes().starts_with(b"\"") {
+        header::ETAG
+    } else {
+        header::LAST_MODIFIED
+    };
+    cached.get(validator) == Some(if_range)
+}
+
+/// Builds the response of a cache hit, honoring the `Range` request header.
+pub fn respond(request: &HeaderMap, cached: CachedResponse) -> Response<Body> {
+    let CachedResponse { parts, body, .. } = cached;
+    let len = body.len() as u64;
+
+    let ranges = match request.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range)
+            if parts.status == StatusCode::OK && if_range_matches(request, &parts.headers) =>
+        {
+            parse(range, len)
+        }
+        _ => ByteRanges::Full,
+    };
+
+    let mut response = Response::from_parts(parts, Body::empty());
+    let headers = response.headers_mut();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    match ranges {
+        ByteRanges::Full => {
+            *response.body_mut() = Body::from(body);
+        }
+        ByteRanges::Unsatisfiable => {
+            headers.remove(header::CONTENT_TYPE);
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+            headers.insert(header::CONTENT_RANGE, content_range(None, len));
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+        }
+        ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let part = slice(&body, &range);
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(part.len()));
+            headers.insert(header::CONTENT_RANGE, content_range(Some(range), len));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            *response.body_mut() = Body::from(part);
+        }
+        ByteRanges::Partial(ranges) => {
+            let boundary = boundary();
+            let content_type = headers.remove(header::CONTENT_TYPE);
+            let multipart = multipart(&body, &ranges, content_type.as_ref(), &boundary, len);
+            let multipart_type = format!("multipart/byteranges; boundary={boundary}");
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(&multipart_type).expect("boundary is ascii"),
+            );
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(multipart.len()));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            *response.body_mut() = Body::from(multipart);
+        }
+    }
+
+    response
+}
+
+fn slice(body: &Bytes, range: &RangeInclusive<u64>) -> Bytes {
+    body.slice(*range.start() as usize..=*range.end() as usize)
+}
+
+fn content_range(range: Option<RangeInclusive<u64>>, len: u64) -> HeaderValue {
+    let value = match range {
+        Some(range) => format!("bytes {}-{}/{len}", range.start(), range.end()),
+        None => format!("bytes */{len}"),
+    };
+    HeaderValue::from_str(&value).expect("content range is ascii")
+}
+
+/// A boundary unlikely to appear inside the body.
+fn boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.subsec_nanos())
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("cache-response-{nanos:08x}{count:08x}")
+}
+
+fn multipart(
+    body: &Bytes,
+    ranges: &[RangeInclusive<u64>],
+    content_type: Option<&HeaderValue>,
+    boundary: &str,
+    len: u64,
+) -> Bytes {
+    let mut multipart = Vec::new();
+    for range in ranges {
+        multipart.extend_from_slice(format!("\r\n--{boundary}\r\n").as_bytes());
+        if let Some(content_type) = content_type {
+            multipart.extend_from_slice(b"Content-Type: ");
+            multipart.extend_from_slice(content_type.as_bytes());
+            multipart.extend_from_slice(b"\r\n");
+        }
+        multipart.extend_from_slice(b"Content-Range: ");
+        multipart.extend_from_slice(content_range(Some(range.clone()), len).as_bytes());
+        multipart.extend_from_slice(b"\r\n\r\n");
+        multipart.extend_from_slice(&slice(body, range));
+    }
+    multipart.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    Bytes::from(multipart)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::http::Request;
+    use std::time::Instant;
+
+    const BODY: &[u8] = b"0123456789abcdefghij";
+    const ETAG: &str = "\"0123456789abcdef0123456789abcdef\"";
+
+    fn cached() -> CachedResponse {
+        let (parts, ()) = Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::ETAG, ETAG)
+            .body(())
+            .unwrap()
+            .into_parts();
+        CachedResponse {
+            parts,
+            body: Bytes::from_static(BODY),
+            stored_at: Instant::now(),
+            expires_at: None,
+        }
+    }
+
+    fn request(range: &'static str, if_range: Option<&'static str>) -> HeaderMap {
+        let mut request = Request::get("/").header(header::RANGE, range);
+        if let Some(if_range) = if_range {
+            request = request.header(header::IF_RANGE, if_range);
+        }
+        request.body(()).unwrap().into_parts().0.headers
+    }
+
+    async fn body(response: Response<Body>) -> Bytes {
+        to_bytes(response.into_body(), usize::MAX).await.unwrap()
+    }
+
+    #[test]
+    fn parses_the_ranges() {
+        assert_eq!(parse("bytes=0-4", 20), ByteRanges::Partial(vec![0..=4]));
+        assert_eq!(parse("bytes=15-", 20), ByteRanges::Partial(vec![15..=19]));
+        assert_eq!(parse("bytes=10-99", 20), ByteRanges::Partial(vec![10..=19]));
+        assert_eq!(parse("items=0-4", 20), ByteRanges::Full);
+        assert_eq!(parse("bytes=4-0", 20), ByteRanges::Full);
+        assert_eq!(parse("bytes=20-", 20), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn parses_the_suffix_ranges() {
+        assert_eq!(parse("bytes=-5", 20), ByteRanges::Partial(vec![15..=19]));
+        // longer than the body
+        assert_eq!(parse("bytes=-50", 20), ByteRanges::Partial(vec![0..=19]));
+        assert_eq!(parse("bytes=-0", 20), ByteRanges::Unsatisfiable);
+        assert_eq!(parse("bytes=-5", 0), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn merges_the_overlapping_and_adjacent_ranges() {
+        assert_eq!(
+            parse("bytes=10-14, 0-4, 3-6, 7-8", 20),
+            ByteRanges::Partial(vec![0..=8, 10..=14])
+        );
+        assert_eq!(
+            parse("bytes=0-, -5, 2-3", 20),
+            ByteRanges::Partial(vec![0..=19])
+        );
+        let many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse(&many, 20), ByteRanges::Full);
+    }
+
+    #[tokio::test]
+    async fn answers_a_single_range() {
+        let response = respond(&request("bytes=-5", None), cached());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 15-19/20");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "5");
+        assert_eq!(body(response).await, &BODY[15..]);
+    }
+
+    #[tokio::test]
+    async fn answers_the_unsatisfiable_ranges() {
+        let response = respond(&request("bytes=20-30", None), cached());
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */20");
+        assert!(!response.headers().contains_key(header::CONTENT_TYPE));
+        assert_eq!(body(response).await, "");
+    }
+
+    #[tokio::test]
+    async fn answers_multiple_ranges_as_multipart() {
+        let response = respond(&request("bytes=15-, 0-1, 1-2", None), cached());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_owned();
+        let length = response.headers()[header::CONTENT_LENGTH].clone();
+        let body = body(response).await;
+        assert_eq!(length, body.len().to_string());
+        let expected = format!(
+            "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-2/20\r\n\r\n012\
+             \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 15-19/20\r\n\r\nfghij\
+             \r\n--{boundary}--\r\n"
+        );
+        assert_eq!(body, expected);
+    }
+
+    #[tokio::test]
+    async fn honors_if_range() {
+        let response = respond(&request("bytes=0-4", Some(ETAG)), cached());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, &BODY[..5]);
+
+        // the partial copy of the client is outdated
+        for outdated in ["\"other\"", "W/\"0123456789abcdef0123456789abcdef\""] {
+            let response = respond(&request("bytes=0-4", Some(outdated)), cached());
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(!response.headers().contains_key(header::CONTENT_RANGE));
+            assert_eq!(body(response).await, BODY);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use axum::http::HeaderValue;
+use std::fmt;
+use std::time::Duration;
+
+/// How the cache handled a request, sent in the status header of the response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    /// Served from a fresh cached entry.
+    Hit,
+    /// Not cached yet, the response of the inner service is being stored.
+    Miss,
+    /// Not cacheable, the response of the inner service is sent untouched.
+    Bypass,
+    /// Served from an expired entry while it gets refreshed.
+    Stale,
+    /// The cached entry had expired, the response of the inner service replaces it.
+    Expired,
+}
+
+impl CacheStatus {
+    pub const ALL: [CacheStatus; 5] = [
+        CacheStatus::Hit,
+        CacheStatus::Miss,
+        CacheStatus::Bypass,
+        CacheStatus::Stale,
+        CacheStatus::Expired,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+            CacheStatus::Stale => "STALE",
+            CacheStatus::Expired => "EXPIRED",
+        }
+    }
+
+    pub fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(self.as_str())
+    }
+
+    /// Whether the response was served from the cache.
+    pub fn is_cached(self) -> bool {
+        matches!(self, CacheStatus::Hit | CacheStatus::Stale)
+    }
+
+    /// The `Server-Timing` entries reporting the status, and the time taken by the whole
+    /// response, the inner service included on a miss.
+    pub fn server_timing(self, elapsed: Duration) -> HeaderValue {
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        let value = format!("cache;desc=\"{self}\", total;dur={millis:.3}");
+        HeaderValue::from_str(&value).expect("server timing is ascii")
+    }
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
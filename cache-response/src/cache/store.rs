@@ -0,0 +1,501 @@
+use super::backend::{CacheStore, FileStore, MemoryStore, StoreLimits};
+use super::coalesce::{Flight, InFlight};
+use super::key::CacheKey;
+use super::mime;
+use super::CacheStatus;
+use axum::body::Bytes;
+use axum::http::{response::Parts, HeaderName};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Decides which entry is dropped first when the cache is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// The least recently read or written entry is dropped first.
+    #[default]
+    Lru,
+    /// The oldest inserted entry is dropped first, reads don't refresh it.
+    Fifo,
+}
+
+/// Rough memory used by a stored `Parts` besides its headers.
+const PARTS_OVERHEAD: usize = std::mem::size_of::<Parts>();
+
+/// A response stored in a [`ResponseCache`].
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    pub parts: Parts,
+    pub body: Bytes,
+    pub stored_at: Instant,
+    /// When the entry stops being fresh, `None` if it never does.
+    pub expires_at: Option<Instant>,
+}
+
+impl CachedResponse {
+    /// Number of bytes the entry accounts for in the cache budget.
+    pub fn weight(&self) -> usize {
+        weight(&self.parts, &self.body)
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| Instant::now() < expires_at)
+    }
+
+    /// How long the entry stays fresh, `None` if it never expires.
+    pub fn ttl_left(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|expires_at| expires_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// How long ago the entry expired, zero while it is fresh.
+    pub fn expired_for(&self) -> Duration {
+        self.expires_at
+            .map(|expires_at| Instant::now().saturating_duration_since(expires_at))
+            .unwrap_or_default()
+    }
+}
+
+/// How long a new entry lives in a [`ResponseCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Expiry {
+    /// Time-to-live of the entry, the cache [`ttl`](ResponseCacheBuilder::ttl) when `None`.
+    pub ttl: Option<Duration>,
+    /// How long the entry is kept once expired, to be served stale.
+    pub grace: Duration,
+}
+
+/// Size of the body plus the header names and values of a response.
+pub(crate) fn weight(parts: &Parts, body: &Bytes) -> usize {
+    let headers: usize = parts
+        .headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    PARTS_OVERHEAD + headers + body.len()
+}
+
+/// The outcome of looking a key up in a [`ResponseCache`].
+#[derive(Clone, Debug)]
+pub enum Lookup {
+    Hit(CachedResponse),
+    /// The entry expired but is still within its grace period.
+    Stale(CachedResponse),
+    /// An entry was stored but outlived its time-to-live and grace period, it has been dropped.
+    Expired,
+    Miss,
+}
+
+impl Lookup {
+    pub fn hit(self) -> Option<CachedResponse> {
+        match self {
+            Lookup::Hit(cached) => Some(cached),
+            _ => None,
+        }
+    }
+}
+
+/// Builder for [`ResponseCache`].
+///
+/// ```ignore
+/// let cache = ResponseCache::builder()
+///     .max_entries(500)
+///     .max_bytes(64 * 1024 * 1024)
+///     .max_entry_bytes(16 * 1024 * 1024)
+///     .ttl(Duration::from_secs(3600))
+///     .eviction(EvictionPolicy::Lru)
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ResponseCacheBuilder {
+    limits: StoreLimits,
+}
+
+impl ResponseCacheBuilder {
+    /// Maximum number of entries, defaults to [`DEFAULT_MAX_ENTRIES`](super::DEFAULT_MAX_ENTRIES).
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.limits.max_entries = max_entries;
+        self
+    }
+
+    /// Maximum number of bytes kept in memory, unbounded by default.
+    ///
+    /// Each entry weighs its body plus its headers (see [`CachedResponse::weight`]),
+    /// entries are evicted until the total fits the budget.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.limits.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Entries weighing more are never stored, unbounded by default.
+    ///
+    /// It is also capped by [`max_bytes`](Self::max_bytes) since an entry larger than
+    /// the whole budget would evict everything else and then itself.
+    pub fn max_entry_bytes(mut self, max_entry_bytes: usize) -> Self {
+        self.limits.max_entry_bytes = Some(max_entry_bytes);
+        self
+    }
+
+    /// How long an entry stays fresh after being stored, forever by default.
+    ///
+    /// It can be overridden for each entry with [`ResponseCache::insert_with`].
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.limits.ttl = Some(ttl);
+        self
+    }
+
+    /// Which entry to drop when a limit is reached, defaults to [`EvictionPolicy::Lru`].
+    pub fn eviction(mut self, eviction: EvictionPolicy) -> Self {
+        self.limits.eviction = eviction;
+        self
+    }
+
+    /// A cache keeping its entries in memory, see [`MemoryStore`].
+    pub fn build(self) -> ResponseCache {
+        ResponseCache::with_store(MemoryStore::new(self.limits))
+    }
+
+    /// A cache persisted in `dir`, loading the entries stored by a previous run,
+    /// see [`FileStore`].
+    pub async fn open(self, dir: impl Into<PathBuf>) -> io::Result<ResponseCache> {
+        let store = FileStore::open(dir, self.limits).await?;
+        Ok(ResponseCache::with_store(store))
+    }
+}
+
+/// Stores responses, keyed by [`CacheKey`], in a [`CacheStore`].
+///
+/// Cloning a `ResponseCache` is cheap and the clones share the same entries,
+/// so one cache is usually built per router and handed to the middleware.
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    store: Arc<dyn CacheStore>,
+    flights: InFlight,
+    counters: Arc<Counters>,
+}
+
+/// A snapshot of the [`ResponseCache`] size and counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// Weight of the entries, see [`CachedResponse::weight`].
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub bypasses: u64,
+    pub stale: u64,
+    pub expired: u64,
+    /// Entries dropped to respect the entry limit or the byte budget.
+    pub evictions: u64,
+    /// Misses answered from the entry populated by a concurrent request.
+    pub coalesced: u64,
+}
+
+/// Describes a stored entry, see [`ResponseCache::entries`].
+#[derive(Clone, Debug, Serialize)]
+pub struct EntryInfo {
+    pub key: String,
+    pub uri: String,
+    pub encoding: &'static str,
+    pub bytes: usize,
+    pub age_secs: u64,
+    /// Seconds until the entry expires, `None` if it never does.
+    pub ttl_secs: Option<u64>,
+}
+
+/// Requests counted for a content type, see [`ResponseCache::content_type_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ContentTypeStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub bypasses: u64,
+    pub stale: u64,
+    pub expired: u64,
+}
+
+impl ContentTypeStats {
+    pub fn count(&self, status: CacheStatus) -> u64 {
+        match status {
+            CacheStatus::Hit => self.hits,
+            CacheStatus::Miss => self.misses,
+            CacheStatus::Bypass => self.bypasses,
+            CacheStatus::Stale => self.stale,
+            CacheStatus::Expired => self.expired,
+        }
+    }
+
+    pub fn requests(&self) -> u64 {
+        CacheStatus::ALL
+            .iter()
+            .map(|&status| self.count(status))
+            .sum()
+    }
+
+    /// Share of the requests served from the cache, zero without requests.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.requests() {
+            0 => 0.0,
+            requests => (self.hits + self.stale) as f64 / requests as f64,
+        }
+    }
+
+    fn record(&mut self, status: CacheStatus) {
+        let count = match status {
+            CacheStatus::Hit => &mut self.hits,
+            CacheStatus::Miss => &mut self.misses,
+            CacheStatus::Bypass => &mut self.bypasses,
+            CacheStatus::Stale => &mut self.stale,
+            CacheStatus::Expired => &mut self.expired,
+        };
+        *count += 1;
+    }
+}
+
+/// Most content types counted apart, the next ones are counted as `other`.
+const MAX_CONTENT_TYPES: usize = 64;
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bypasses: AtomicU64,
+    stale: AtomicU64,
+    expired: AtomicU64,
+    coalesced: AtomicU64,
+    content_types: Mutex<HashMap<String, ContentTypeStats>>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl ResponseCache {
+    pub fn builder() -> ResponseCacheBuilder {
+        ResponseCacheBuilder::default()
+    }
+
+    /// A cache keeping its entries in `store`.
+    pub fn with_store(store: impl CacheStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            flights: InFlight::default(),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Returns the entry stored for `key`, dropping it if it has expired.
+    pub async fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        self.lookup(key).await.hit()
+    }
+
+    /// Like [`get`](Self::get) but tells an expired entry apart from a missing one.
+    pub async fn lookup(&self, key: &CacheKey) -> Lookup {
+        self.store.get(key).await
+    }
+
+    /// Stores a response, evicting other entries if a limit is exceeded.
+    ///
+    /// Returns `false` when the response is too large to be stored.
+    pub async fn insert(&self, key: CacheKey, parts: Parts, body: Bytes) -> bool {
+        self.insert_with(key, parts, body, Expiry::default())
+            .await
+            .is_some()
+    }
+
+    /// Like [`insert`](Self::insert) with a custom [`Expiry`], returns the stored entry.
+    pub async fn insert_with(
+        &self,
+        key: CacheKey,
+        parts: Parts,
+        body: Bytes,
+        expiry: Expiry,
+    ) -> Option<CachedResponse> {
+        self.flights.unpass(&key);
+        self.store.put(key, parts, body, expiry).await
+    }
+
+    pub async fn remove(&self, key: &CacheKey) -> Option<CachedResponse> {
+        self.store.delete(key).await
+    }
+
+    /// The request headers the last stored response for `key.base()` varies on.
+    pub async fn vary(&self, key: &CacheKey) -> Vec<HeaderName> {
+        self.store.vary(key).await
+    }
+
+    pub async fn clear(&self) {
+        self.store.clear().await;
+    }
+
+    /// Number of entries currently stored.
+    pub async fn len(&self) -> usize {
+        self.store.stats().await.entries
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Weight of all the entries currently stored, see [`CachedResponse::weight`].
+    pub async fn size_bytes(&self) -> usize {
+        self.store.stats().await.bytes
+    }
+
+    /// Largest weight of a single entry, see [`ResponseCacheBuilder::max_entry_bytes`].
+    pub fn max_entry_bytes(&self) -> Option<usize> {
+        self.store.limits().entry_limit()
+    }
+
+    /// Registers a miss for `key`, telling whether another request is already populating it.
+    pub fn join(&self, key: &CacheKey) -> Flight {
+        self.flights.join(key)
+    }
+
+    /// Remembers that the response for `key` can't be cached, so concurrent misses for it
+    /// aren't coalesced until a response gets stored again.
+    pub fn pass(&self, key: &CacheKey) {
+        self.flights.pass(key);
+    }
+
+    /// Counts a miss answered by waiting for a concurrent request.
+    pub fn record_coalesced(&self) {
+        self.counters.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request handled with `status`, whose response has `content_type`.
+    pub fn record(&self, status: CacheStatus, content_type: Option<&str>) {
+        let counter = match status {
+            CacheStatus::Hit => &self.counters.hits,
+            CacheStatus::Miss => &self.counters.misses,
+            CacheStatus::Bypass => &self.counters.bypasses,
+            CacheStatus::Stale => &self.counters.stale,
+            CacheStatus::Expired => &self.counters.expired,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        // `text/css; charset=utf-8` is counted as `text/css`
+        let essence = content_type
+            .and_then(mime::essence)
+            .unwrap_or_else(|| "none".to_owned());
+        let mut content_types = self
+            .counters
+            .content_types
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let essence =
+            match content_types.contains_key(&essence) || content_types.len() < MAX_CONTENT_TYPES {
+                true => essence,
+                false => "other".to_owned(),
+            };
+        content_types.entry(essence).or_default().record(status);
+    }
+
+    /// The requests counted for each content type, sorted by content type.
+    pub fn content_type_stats(&self) -> Vec<(String, ContentTypeStats)> {
+        let content_types = self
+            .counters
+            .content_types
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut stats: Vec<_> = content_types
+            .iter()
+            .map(|(content_type, stats)| (content_type.clone(), *stats))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let store = self.store.stats().await;
+        let counters = &self.counters;
+        CacheStats {
+            entries: store.entries,
+            bytes: store.bytes,
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            bypasses: counters.bypasses.load(Ordering::Relaxed),
+            stale: counters.stale.load(Ordering::Relaxed),
+            expired: counters.expired.load(Ordering::Relaxed),
+            evictions: store.evictions,
+            coalesced: counters.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Describes the stored entries, least recently used first.
+    pub async fn entries(&self) -> Vec<EntryInfo> {
+        self.store.entries().await
+    }
+
+    /// Drops every entry, all variants included, of the resource at `uri`.
+    ///
+    /// Returns the number of dropped entries.
+    pub async fn purge_uri(&self, uri: &str) -> usize {
+        self.store.purge_uri(uri).await
+    }
+
+    /// Drops every entry whose URI starts with `prefix`.
+    ///
+    /// Returns the number of dropped entries.
+    pub async fn purge_prefix(&self, prefix: &str) -> usize {
+        self.store.purge_prefix(prefix).await
+    }
+
+    /// Drops every entry of the resource at `path`, for every query, encoding and
+    /// variant.
+    ///
+    /// Meant for the server functions changing what a page or a file shows, the cache
+    /// being provided as context :
+    ///
+    /// ```ignore
+    /// expect_context::<ResponseCache>().invalidate("/").await;
+    /// ```
+    ///
+    /// Returns the number of dropped entries.
+    pub async fn invalidate(&self, path: &str) -> usize {
+        let purged = self.purge_uri(path).await + self.purge_prefix(&format!("{path}?")).await;
+        log::debug!("invalidated {purged} cache entries of {path}");
+        purged
+    }
+
+    /// Drops every entry whose path starts with `prefix`, like `/pkg/` for the
+    /// files of a rebuild.
+    ///
+    /// Returns the number of dropped entries.
+    pub async fn invalidate_prefix(&self, prefix: &str) -> usize {
+        let purged = self.purge_prefix(prefix).await;
+        log::debug!("invalidated {purged} cache entries under {prefix}");
+        purged
+    }
+
+    /// Maximum number of entries this cache holds.
+    pub fn capacity(&self) -> usize {
+        self.store.limits().max_entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::testing::{get, Origin};
+    use crate::cache::ResponseCacheLayer;
+    use tower::Layer;
+
+    #[tokio::test]
+    async fn invalidates_the_entries_of_absolute_form_requests() {
+        let cache = ResponseCache::default();
+        let service = ResponseCacheLayer::new(cache.clone()).layer(Origin::new());
+
+        let uri = "http://example.com/app.css?v=1";
+        assert_eq!(get(&service, uri).await.unwrap().0, "MISS");
+        assert_eq!(get(&service, "/app.css?v=1").await.unwrap().0, "HIT");
+        assert_eq!(cache.invalidate("/app.css").await, 1);
+        assert_eq!(get(&service, uri).await.unwrap().0, "MISS");
+    }
+}
@@ -0,0 +1,109 @@
+//! An inner service for the tests of the middleware.
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, Response, StatusCode};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Service, ServiceExt};
+
+/// How the [`Origin`] answers the next requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Answer {
+    /// `200 OK` with the number of the call as body.
+    Ok,
+    /// `503 Service Unavailable`.
+    ServerError,
+    /// The service itself fails.
+    Error,
+}
+
+/// Answers `text/css` bodies after a delay, counting the calls.
+#[derive(Clone, Debug)]
+pub struct Origin {
+    calls: Arc<AtomicUsize>,
+    answer: Arc<Mutex<Answer>>,
+    delay: Duration,
+    cache_control: Option<&'static str>,
+}
+
+impl Origin {
+    pub fn new() -> Self {
+        Self {
+            calls: Arc::default(),
+            answer: Arc::new(Mutex::new(Answer::Ok)),
+            delay: Duration::ZERO,
+            cache_control: None,
+        }
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn cache_control(mut self, cache_control: &'static str) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Changes the answer of all the clones.
+    pub fn answer(&self, answer: Answer) {
+        *self.answer.lock().unwrap_or_else(PoisonError::into_inner) = answer;
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Service<Request<Body>> for Origin {
+    type Response = Response<Body>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Response<Body>>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _req: Request<Body>) -> Self::Future {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        let answer = *self.answer.lock().unwrap_or_else(PoisonError::into_inner);
+        let delay = self.delay;
+        let cache_control = self.cache_control;
+        Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            let status = match answer {
+                Answer::Ok => StatusCode::OK,
+                Answer::ServerError => StatusCode::SERVICE_UNAVAILABLE,
+                Answer::Error => return Err(io::Error::other("the origin is down")),
+            };
+            let mut response = Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "text/css");
+            if let Some(cache_control) = cache_control {
+                response = response.header(header::CACHE_CONTROL, cache_control);
+            }
+            Ok(response.body(Body::from(format!("call {call}"))).unwrap())
+        })
+    }
+}
+
+/// Sends a `GET` for `uri`, returning the cache status and the body.
+pub async fn get<S>(service: &S, uri: &str) -> Result<(String, String), S::Error>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone,
+{
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = service.clone().oneshot(request).await?;
+    let status = response.headers()[super::DEFAULT_STATUS_HEADER]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    Ok((status, String::from_utf8(body.to_vec()).unwrap()))
+}
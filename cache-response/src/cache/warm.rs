@@ -0,0 +1,134 @@
+use super::{conditional, encoding, CacheConfig, CacheKey, ResponseCache, IMMUTABLE_CACHE_CONTROL};
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderValue, Method, Response, Uri};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What [`warm_dir`] loaded into the cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WarmReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Loads the cacheable files of `root/dir` into the cache, so the first visitors after
+/// a deploy don't wait for the disk.
+///
+/// Each file is stored as if it was served from `root` : `target/site/pkg/app.wasm` is
+/// stored for `/pkg/app.wasm`. Only the files whose content type matches a
+/// [`CacheConfig`] rule are loaded, nothing is done when
+/// [`CacheConfig::prewarm`] is disabled.
+pub async fn warm_dir(
+    cache: &ResponseCache,
+    config: &CacheConfig,
+    root: impl AsRef<Path>,
+    dir: impl AsRef<Path>,
+) -> io::Result<WarmReport> {
+    let mut report = WarmReport::default();
+    if !config.prewarm {
+        return Ok(report);
+    }
+
+    let root = root.as_ref();
+    let mut dirs = vec![root.join(dir)];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                if let Some(bytes) = warm_file(cache, config, root, path).await? {
+                    report.files += 1;
+                    report.bytes += bytes;
+                }
+            }
+        }
+    }
+
+    log::info!(
+        "warmed the cache with {} files ({} bytes)",
+        report.files,
+        report.bytes
+    );
+    Ok(report)
+}
+
+/// Stores one file, returns its size if it was cacheable.
+async fn warm_file(
+    cache: &ResponseCache,
+    config: &CacheConfig,
+    root: &Path,
+    path: PathBuf,
+) -> io::Result<Option<u64>> {
+    let Some(uri) = site_path(root, &path).and_then(|path| path.parse::<Uri>().ok()) else {
+        return Ok(None);
+    };
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let Some(rule) = config.rule_for_route(uri.path(), mime.as_ref()) else {
+        return Ok(None);
+    };
+    let Some(key) = CacheKey::new(&Method::GET, &uri) else {
+        return Ok(None);
+    };
+
+    // the entries of the previous build, its compressed variants included, must go
+    cache.invalidate(uri.path()).await;
+
+    let metadata = tokio::fs::metadata(&path).await?;
+    if metadata.len() > config.max_body_size as u64 {
+        log::debug!("{} is too large to be cached", path.display());
+        return Ok(None);
+    }
+    let body = Bytes::from(tokio::fs::read(&path).await?);
+
+    // the same headers as a response stored by the layer
+    let mut response = Response::new(Body::empty());
+    let headers = response.headers_mut();
+    let content_type = HeaderValue::from_str(mime.as_ref()).expect("mime type is ascii");
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+    headers.insert(header::CACHE_CONTROL, rule.cache_control.clone());
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(modified) = metadata.modified() {
+        let modified = httpdate::fmt_http_date(modified);
+        let modified = HeaderValue::from_str(&modified).expect("http date is ascii");
+        headers.insert(header::LAST_MODIFIED, modified);
+    }
+    if !config.encodings.is_empty() {
+        encoding::vary_on_accept_encoding(headers);
+    }
+    let (mut parts, _) = response.into_parts();
+    conditional::add_validators(&mut parts, &body);
+
+    // and under its content-hashed URL
+    let hashed = config
+        .fingerprints
+        .hashed(uri.path())
+        .and_then(|hashed| hashed.parse::<Uri>().ok())
+        .and_then(|hashed| CacheKey::new(&Method::GET, &hashed));
+    if let Some(hashed) = hashed {
+        let mut parts = parts.clone();
+        let immutable = HeaderValue::from_static(IMMUTABLE_CACHE_CONTROL);
+        parts.headers.insert(header::CACHE_CONTROL, immutable);
+        let expiry = config.expiry(rule);
+        cache.insert_with(hashed, parts, body.clone(), expiry).await;
+    }
+
+    let size = body.len() as u64;
+    let stored = cache
+        .insert_with(key, parts, body, config.expiry(rule))
+        .await;
+    Ok(stored.map(|_| size))
+}
+
+/// The path a file is served at, relative to `root`.
+pub(crate) fn site_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let segments: Vec<&str> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    Some(format!("/{}", segments.join("/")))
+}
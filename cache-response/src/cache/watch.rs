@@ -0,0 +1,105 @@
+use super::warm::site_path;
+use super::{CacheConfig, Encoding, ResponseCache};
+use crate::assets::AssetManifest;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+/// Evicts the entries of the files changing under a directory, until dropped.
+///
+/// Meant for development, `cargo leptos watch` rebuilding `site-root` while the server
+/// keeps serving the previous build :
+///
+/// ```ignore
+/// let _watcher = SiteWatcher::start(cache.clone(), &cache_config, site_root)?;
+/// ```
+///
+/// A changed file evicts every entry of the path it is served at, of its content-hashed
+/// URL and, for a pre-compressed sibling like `app.wasm.br`, of the file it compresses.
+/// The hashed URLs stay the ones of the [`AssetManifest`] computed at startup, a rebuilt
+/// file would be served under its old hash : leave the assets unhashed while watching.
+#[derive(Debug)]
+pub struct SiteWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl SiteWatcher {
+    /// Watches `root`, must be called from a tokio runtime.
+    pub fn start(
+        cache: ResponseCache,
+        config: &CacheConfig,
+        root: impl AsRef<Path>,
+    ) -> notify::Result<Self> {
+        // the events name the files under the path given to the watcher
+        let root = std::fs::canonicalize(root)?;
+        let (sender, mut changes) = mpsc::unbounded_channel::<PathBuf>();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    for path in event.paths {
+                        _ = sender.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => log::warn!("failed to watch the site files: {error}"),
+            })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        log::info!(
+            "evicting the cache entries of the files changing in {}",
+            root.display()
+        );
+
+        let manifest = config.fingerprints.clone();
+        // ends with the watcher, which holds the sender
+        tokio::spawn(async move {
+            while let Some(file) = changes.recv().await {
+                for path in served_paths(&root, &manifest, &file) {
+                    let evicted = cache.invalidate(&path).await;
+                    if evicted > 0 {
+                        log::info!("{path} changed, evicted {evicted} cache entries");
+                    }
+                }
+            }
+        });
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// The paths whose entries are outdated when `file` changes.
+fn served_paths(root: &Path, manifest: &AssetManifest, file: &Path) -> Vec<String> {
+    let Some(path) = site_path(root, file) else {
+        return Vec::new();
+    };
+    // a sibling is rebuilt with the file it compresses, whose variants it serves
+    let path = Encoding::COMPRESSED
+        .iter()
+        .filter_map(|encoding| encoding.extension())
+        .find_map(|extension| path.strip_suffix(extension)?.strip_suffix('.'))
+        .map(str::to_owned)
+        .unwrap_or(path);
+    let hashed = manifest.hashed(&path).map(str::to_owned);
+    std::iter::once(path).chain(hashed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_served_paths() {
+        let root = Path::new("/srv/site");
+        let manifest = AssetManifest::new([(
+            "/pkg/app.css".to_owned(),
+            "/pkg/app.0123456789abcdef.css".to_owned(),
+        )]);
+        let served = |file: &str| served_paths(root, &manifest, Path::new(file));
+
+        assert_eq!(
+            served("/srv/site/pkg/app.css"),
+            ["/pkg/app.css", "/pkg/app.0123456789abcdef.css"]
+        );
+        assert_eq!(served("/srv/site/pkg/app.wasm.br"), ["/pkg/app.wasm"]);
+        assert_eq!(served("/srv/site/favicon.ico"), ["/favicon.ico"]);
+        assert!(served("/elsewhere/app.css").is_empty());
+    }
+}
@@ -1,7 +1,12 @@
 pub mod app;
+pub mod assets;
 #[cfg(feature = "ssr")]
 pub mod cache;
 pub mod error_template;
+#[cfg(feature = "ssr")]
+pub mod security;
+#[cfg(feature = "ssr")]
+pub mod server;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
@@ -1,13 +1,9 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() {
-    use axum::middleware as AxumMiddleware;
-    use axum::Router;
-    use cache_response::app::*;
-    use cache_response::cache;
+    use cache_response::server::{Server, ServerSettings};
     use leptos::logging as console;
     use leptos::prelude::*;
-    use leptos_axum::{generate_route_list, LeptosRoutes};
 
     // Setting get_configuration(None) means we'll be using cargo-leptos's env values
     // For deployment these variables are:
@@ -17,23 +13,14 @@ async fn main() {
     let conf = get_configuration(None).unwrap();
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
-    let routes = generate_route_list(App);
-
-    // build our application with a route
-    let app = Router::new()
-        .leptos_routes(&leptos_options, routes, {
-            let leptos_options = leptos_options.clone();
-            move || shell(leptos_options.clone())
-        })
-        .fallback(leptos_axum::file_and_error_handler(shell))
-        .layer(AxumMiddleware::from_fn(cache::handle))
-        .with_state(leptos_options);
+    // `CACHE_DIR`, `CACHE_ADMIN_SECRET` and `CACHE_PREWARM=false` tune the cache
+    let server = Server::build(leptos_options, ServerSettings::from_env())
+        .await
+        .expect("failed to open the cache directory");
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
     console::log!("listening on http://{}", &addr);
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    server.serve(listener).await.unwrap();
 }
 
 #[cfg(not(feature = "ssr"))]
@@ -0,0 +1,331 @@
+use axum::http::HeaderValue;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures::executor::block_on;
+use futures::StreamExt;
+use hydration_context::{SharedContext, SsrSharedContext};
+use leptos::config::{Env, LeptosOptions};
+use leptos::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// A `Content-Security-Policy` built directive by directive.
+///
+/// The default policy only lets the page load resources from its own origin, plus
+/// what a leptos app needs : the `wasm-unsafe-eval` of the wasm module and `data:`
+/// images. The inline scripts and styles leptos adds to the pages, like the ones of
+/// `HydrationScripts` and `AutoReload`, are allowed by their hash, see
+/// [`InlineHashes`].
+///
+/// ```ignore
+/// let csp = ContentSecurityPolicy::default()
+///     .source("img-src", "https://images.example.com")
+///     .auto_reload(&leptos_options)
+///     .inline(&InlineHashes::leptos(&leptos_options));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentSecurityPolicy {
+    directives: Vec<(String, Vec<String>)>,
+}
+
+impl Default for ContentSecurityPolicy {
+    fn default() -> Self {
+        Self::new()
+            .directive("default-src", ["'self'"])
+            .directive("script-src", ["'self'", "'wasm-unsafe-eval'"])
+            .directive("style-src", ["'self'"])
+            .directive("img-src", ["'self'", "data:"])
+            .directive("connect-src", ["'self'"])
+            .directive("object-src", ["'none'"])
+            .directive("base-uri", ["'self'"])
+            .directive("form-action", ["'self'"])
+            .directive("frame-ancestors", ["'none'"])
+    }
+}
+
+impl ContentSecurityPolicy {
+    /// A policy without any directive, allowing everything.
+    pub fn new() -> Self {
+        Self {
+            directives: Vec::new(),
+        }
+    }
+
+    /// Sets the sources of a directive, replacing the ones it had.
+    pub fn directive<S: Into<String>>(
+        mut self,
+        name: &str,
+        sources: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let sources = sources.into_iter().map(Into::into).collect();
+        match self.directives.iter_mut().find(|(known, _)| known == name) {
+            Some((_, known)) => *known = sources,
+            None => self.directives.push((name.to_owned(), sources)),
+        }
+        self
+    }
+
+    /// Adds a source to a directive, creating the directive if needed.
+    pub fn source(mut self, name: &str, source: impl Into<String>) -> Self {
+        let source = source.into();
+        match self.directives.iter_mut().find(|(known, _)| known == name) {
+            Some((_, sources)) if !sources.contains(&source) => sources.push(source),
+            Some(_) => {}
+            None => self.directives.push((name.to_owned(), vec![source])),
+        }
+        self
+    }
+
+    /// Drops a directive.
+    pub fn without(mut self, name: &str) -> Self {
+        self.directives.retain(|(known, _)| known != name);
+        self
+    }
+
+    /// Lets `AutoReload` open its websocket when `cargo leptos watch` serves the app,
+    /// nothing changes in production.
+    pub fn auto_reload(self, options: &LeptosOptions) -> Self {
+        match options.env {
+            Env::DEV => self
+                .source("connect-src", "ws:")
+                .source("connect-src", "wss:"),
+            Env::PROD => self,
+        }
+    }
+
+    /// Allows the trusted inline scripts and styles by their hash.
+    pub fn inline(mut self, hashes: &InlineHashes) -> Self {
+        for (directive, hashes) in [
+            ("script-src", &hashes.scripts),
+            ("style-src", &hashes.styles),
+        ] {
+            // the hashes go where the browser looks for the sources
+            let directive = match self.has(directive) || !self.has("default-src") {
+                true => directive,
+                false => "default-src",
+            };
+            if hashes.is_empty() || !self.has(directive) {
+                continue;
+            }
+            self = hashes
+                .iter()
+                .fold(self, |policy, hash| policy.source(directive, hash.clone()));
+        }
+        self
+    }
+
+    /// The value of the header.
+    pub fn header_value(&self) -> HeaderValue {
+        let value = self
+            .directives
+            .iter()
+            .map(|(name, sources)| match sources.is_empty() {
+                true => name.clone(),
+                false => format!("{name} {}", sources.join(" ")),
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&value).expect("content security policy is ascii")
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.directives.iter().any(|(known, _)| known == name)
+    }
+}
+
+/// The hashes of trusted inline `<script>` and `<style>` elements, as CSP sources like
+/// `'sha256-...'`.
+///
+/// The hashes are computed once, from content known at startup, and never from the
+/// pages served : an element injected into a page must not be allowed. Unlike the
+/// nonce leptos draws for each render, they stay valid when a page is served from the
+/// cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InlineHashes {
+    pub scripts: Vec<String>,
+    pub styles: Vec<String>,
+}
+
+impl InlineHashes {
+    /// The scripts leptos inlines in the pages of an app without resources : the ones
+    /// of `HydrationScripts` and `AutoReload`, rendered with `options`, and the
+    /// bootstrap of the hydration context. The pages resolving resources inline their
+    /// data, and need the leptos nonce instead.
+    pub fn leptos(options: &LeptosOptions) -> Self {
+        let head = view! {
+            <AutoReload options=options.clone()/>
+            <HydrationScripts options=options.clone()/>
+        }
+        .to_html();
+        bootstrap()
+            .iter()
+            .fold(Self::of(&head), |hashes, script| hashes.script(script))
+    }
+
+    /// Allows an inline script with this exact content.
+    pub fn script(mut self, content: &str) -> Self {
+        push_hash(&mut self.scripts, content);
+        self
+    }
+
+    /// Allows an inline style with this exact content.
+    pub fn style(mut self, content: &str) -> Self {
+        push_hash(&mut self.styles, content);
+        self
+    }
+
+    /// Hashes the inline elements of trusted markup, the scripts loaded with a `src`
+    /// being allowed by the sources of the policy instead.
+    fn of(html: &str) -> Self {
+        Self {
+            scripts: inline_hashes(html, "script"),
+            styles: inline_hashes(html, "style"),
+        }
+    }
+}
+
+/// The scripts the hydration context of leptos writes at the end of a page without
+/// resources, rendered by the context leptos_axum gives each request.
+fn bootstrap() -> Vec<String> {
+    let context = SsrSharedContext::new();
+    // nothing is pending, the stream is ready at once
+    context
+        .pending_data()
+        .map(|scripts| block_on(scripts.collect()))
+        .unwrap_or_default()
+}
+
+fn push_hash(hashes: &mut Vec<String>, content: &str) {
+    let hash = format!("'sha256-{}'", STANDARD.encode(Sha256::digest(content)));
+    if !hashes.contains(&hash) {
+        hashes.push(hash);
+    }
+}
+
+fn inline_hashes(html: &str, element: &str) -> Vec<String> {
+    // ascii lowercase keeps the offsets of the original
+    let lowercase = html.to_ascii_lowercase();
+    let open = format!("<{element}");
+    let close = format!("</{element}");
+    let mut hashes = Vec::new();
+    let mut rest = 0;
+    while let Some(start) = lowercase[rest..].find(&open).map(|start| rest + start) {
+        let after = start + open.len();
+        rest = after;
+        // `<scripts>` isn't a script
+        if !lowercase[after..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(content) = lowercase[after..].find('>').map(|end| after + end + 1) else {
+            break;
+        };
+        let Some(end) = lowercase[content..].find(&close).map(|end| content + end) else {
+            break;
+        };
+        rest = end;
+        let external = lowercase[after..content]
+            .split_ascii_whitespace()
+            .any(|attribute| attribute.starts_with("src="));
+        let content = &html[content..end];
+        if !external && !content.is_empty() {
+            push_hash(&mut hashes, content);
+        }
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(content: &str) -> String {
+        format!("'sha256-{}'", STANDARD.encode(Sha256::digest(content)))
+    }
+
+    #[test]
+    fn hashes_the_inline_elements() {
+        let html = r#"<!DOCTYPE html><html><head>
+            <link rel="modulepreload" href="/pkg/app.js" nonce="abc">
+            <script type="module" nonce="abc">import("/pkg/app.js")</script>
+            <script src="/pkg/other.js"></script>
+            <SCRIPT>console.log(1)</SCRIPT>
+            <scripts>not a script</scripts>
+            <style>body { color: blue; }</style>
+        </head></html>"#;
+        let hashes = InlineHashes::of(html);
+        assert_eq!(
+            hashes.scripts,
+            [hash(r#"import("/pkg/app.js")"#), hash("console.log(1)")]
+        );
+        assert_eq!(hashes.styles, [hash("body { color: blue; }")]);
+        assert_eq!(
+            InlineHashes::of("<p>no script</p>"),
+            InlineHashes::default()
+        );
+    }
+
+    #[test]
+    fn adds_the_hashes_to_the_policy() {
+        let hashes = InlineHashes::of("<script>run()</script><style>p{}</style>");
+        let policy = ContentSecurityPolicy::new()
+            .directive("default-src", ["'self'"])
+            .directive("script-src", ["'self'"])
+            .inline(&hashes)
+            .header_value();
+        assert_eq!(
+            policy,
+            format!(
+                "default-src 'self' {}; script-src 'self' {}",
+                hash("p{}"),
+                hash("run()")
+            )
+        );
+    }
+
+    #[test]
+    fn builds_the_directives() {
+        let policy = ContentSecurityPolicy::new()
+            .directive("img-src", ["'self'"])
+            .source("img-src", "data:")
+            .source("img-src", "data:")
+            .directive("upgrade-insecure-requests", Vec::<String>::new())
+            .source("frame-src", "'none'")
+            .without("frame-src");
+        assert_eq!(
+            policy.header_value(),
+            "img-src 'self' data:; upgrade-insecure-requests"
+        );
+        // without a `script-src` nor a `default-src`, everything is allowed already
+        let hashes = InlineHashes::default().script("run()");
+        assert_eq!(policy.clone().inline(&hashes), policy);
+    }
+
+    #[test]
+    fn hashes_the_scripts_of_leptos() {
+        let options = LeptosOptions::builder().output_name("app").build();
+        let hashes = InlineHashes::leptos(&options);
+        // the hydration script and the bootstrap, `AutoReload` only renders under
+        // `cargo leptos watch`
+        assert_eq!(hashes.scripts.len(), 3, "{hashes:?}");
+        assert_eq!(
+            bootstrap(),
+            [
+                "__RESOLVED_RESOURCES=[];__SERIALIZED_ERRORS=[];__PENDING_RESOURCES=[];__RESOURCE_RESOLVERS=[];",
+                "__INCOMPLETE_CHUNKS=[];",
+            ]
+        );
+        for script in bootstrap() {
+            assert!(hashes.scripts.contains(&hash(&script)));
+        }
+        assert!(hashes.styles.is_empty());
+        assert_eq!(
+            InlineHashes::default()
+                .script("run()")
+                .script("run()")
+                .style("p{}"),
+            InlineHashes {
+                scripts: vec![hash("run()")],
+                styles: vec![hash("p{}")],
+            }
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use super::ContentSecurityPolicy;
+use axum::body::Body;
+use axum::http::{header, HeaderName, HeaderValue, Request, Response, StatusCode};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Default `Referrer-Policy`, the origin only is sent to other sites.
+pub const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+
+/// Default `Strict-Transport-Security`, two years of https, ignored by the browsers
+/// over plain http.
+pub const DEFAULT_STRICT_TRANSPORT_SECURITY: &str = "max-age=63072000";
+
+/// Configuration of the [`SecurityHeadersLayer`].
+///
+/// By default the responses get the [`ContentSecurityPolicy::default`], along with
+/// `X-Content-Type-Options: nosniff`, the [`DEFAULT_REFERRER_POLICY`], the
+/// [`DEFAULT_STRICT_TRANSPORT_SECURITY`] and the `same-origin` opener and resource
+/// policies :
+///
+/// ```ignore
+/// let config = SecurityConfig::default()
+///     .content_security_policy(ContentSecurityPolicy::default().auto_reload(&leptos_options))
+///     .cross_origin_embedder_policy(HeaderValue::from_static("require-corp"))
+///     .without(header::STRICT_TRANSPORT_SECURITY);
+/// ```
+///
+/// A header already set by the inner service is kept as is.
+#[derive(Clone, Debug)]
+pub struct SecurityConfig {
+    content_security_policy: Option<HeaderValue>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self::new()
+            .content_security_policy(ContentSecurityPolicy::default())
+            .header(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            )
+            .referrer_policy(HeaderValue::from_static(DEFAULT_REFERRER_POLICY))
+            .strict_transport_security(HeaderValue::from_static(DEFAULT_STRICT_TRANSPORT_SECURITY))
+            .cross_origin_opener_policy(HeaderValue::from_static("same-origin"))
+            .cross_origin_resource_policy(HeaderValue::from_static("same-origin"))
+    }
+}
+
+impl SecurityConfig {
+    /// A configuration without any header.
+    pub fn new() -> Self {
+        Self {
+            content_security_policy: None,
+            headers: Vec::new(),
+        }
+    }
+
+    /// The policy sent with every response but the `304`.
+    pub fn content_security_policy(mut self, csp: ContentSecurityPolicy) -> Self {
+        self.content_security_policy = Some(csp.header_value());
+        self
+    }
+
+    /// Sends `value` in the `name` header, replacing the value configured before.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.retain(|(known, _)| *known != name);
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Stops sending a header, the `Content-Security-Policy` included.
+    pub fn without(mut self, name: HeaderName) -> Self {
+        if name == header::CONTENT_SECURITY_POLICY {
+            self.content_security_policy = None;
+        }
+        self.headers.retain(|(known, _)| *known != name);
+        self
+    }
+
+    pub fn referrer_policy(self, value: HeaderValue) -> Self {
+        self.header(header::REFERRER_POLICY, value)
+    }
+
+    pub fn strict_transport_security(self, value: HeaderValue) -> Self {
+        self.header(header::STRICT_TRANSPORT_SECURITY, value)
+    }
+
+    pub fn cross_origin_opener_policy(self, value: HeaderValue) -> Self {
+        self.header(CROSS_ORIGIN_OPENER_POLICY, value)
+    }
+
+    pub fn cross_origin_embedder_policy(self, value: HeaderValue) -> Self {
+        self.header(CROSS_ORIGIN_EMBEDDER_POLICY, value)
+    }
+
+    pub fn cross_origin_resource_policy(self, value: HeaderValue) -> Self {
+        self.header(CROSS_ORIGIN_RESOURCE_POLICY, value)
+    }
+}
+
+const CROSS_ORIGIN_OPENER_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-opener-policy");
+const CROSS_ORIGIN_EMBEDDER_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-embedder-policy");
+const CROSS_ORIGIN_RESOURCE_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-resource-policy");
+
+/// Adds the security headers of a [`SecurityConfig`] to the responses.
+///
+/// Placed inside the [`ResponseCacheLayer`](crate::cache::ResponseCacheLayer), the
+/// headers are stored with the responses :
+///
+/// ```ignore
+/// let app = SecurityHeadersLayer::new(SecurityConfig::default()).layer(app);
+/// let app = ResponseCacheLayer::with_config(cache, cache_config).layer(app);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SecurityHeadersLayer {
+    config: Arc<SecurityConfig>,
+}
+
+impl SecurityHeadersLayer {
+    pub fn new(config: SecurityConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SecurityHeadersService<T> {
+    inner: T,
+    config: Arc<SecurityConfig>,
+}
+
+impl<T> Service<Request<Body>> for SecurityHeadersService<T>
+where
+    T: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = T::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    /// - **Headers :** The headers of the [`SecurityConfig`] missing from the response are added.
+    /// - **Bodies :** The body is left as is, a page streams while it renders.
+    /// - **Not Modified :** A `304` gets no policy, the browser keeps the one of the page it revalidates.
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // the ready service is the one polled, keep it and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let mut response = inner.call(req).await?;
+            let headers = response.headers_mut();
+            for (name, value) in &config.headers {
+                if !headers.contains_key(name) {
+                    headers.insert(name.clone(), value.clone());
+                }
+            }
+
+            if let Some(csp) = &config.content_security_policy {
+                let not_modified = response.status() == StatusCode::NOT_MODIFIED;
+                let headers = response.headers_mut();
+                if !not_modified && !headers.contains_key(header::CONTENT_SECURITY_POLICY) {
+                    headers.insert(header::CONTENT_SECURITY_POLICY, csp.clone());
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheConfig, PageConfig, ResponseCache, ResponseCacheLayer};
+    use crate::security::InlineHashes;
+    use axum::body::{to_bytes, Bytes, HttpBody};
+    use axum::http::HeaderMap;
+    use axum::response::Html;
+    use axum::routing::get;
+    use axum::Router;
+    use http_body::Frame;
+    use std::future::poll_fn;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const HYDRATION: &str = r#"import("/pkg/app.js")"#;
+    const PAGE: &str = r#"<!DOCTYPE html><html><head><script type="module" nonce="abc">import("/pkg/app.js")</script></head><body></body></html>"#;
+    const INJECTED: &str = "alert(document.cookie)";
+
+    fn router() -> Router {
+        Router::new()
+            .route("/", get(|| async { Html(PAGE) }))
+            .route(
+                "/comments",
+                get(|| async {
+                    Html(format!(
+                        "<html><head><script>{HYDRATION}</script></head><body><p><script>{INJECTED}</script></p></body></html>"
+                    ))
+                }),
+            )
+            .route(
+                "/stream",
+                get(|| async { Html(Body::new(Endless(Some(Bytes::from_static(b"<html><head>"))))) }),
+            )
+            .route(
+                "/pkg/app.css",
+                get(|| async { ([(header::CONTENT_TYPE, "text/css")], "body {}") }),
+            )
+            .route(
+                "/framed",
+                get(|| async {
+                    let csp = "frame-ancestors 'self'";
+                    (
+                        [(header::CONTENT_SECURITY_POLICY, csp)],
+                        Html("<p>framed</p>"),
+                    )
+                }),
+            )
+    }
+
+    /// A page whose end never comes.
+    struct Endless(Option<Bytes>);
+
+    impl HttpBody for Endless {
+        type Data = Bytes;
+        type Error = axum::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            match self.0.take() {
+                Some(head) => Poll::Ready(Some(Ok(Frame::data(head)))),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    fn trusted() -> SecurityConfig {
+        let hashes = InlineHashes::default().script(HYDRATION);
+        SecurityConfig::default()
+            .content_security_policy(ContentSecurityPolicy::default().inline(&hashes))
+    }
+
+    async fn get_headers<S>(service: &S, uri: &str) -> (HeaderMap, String)
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone,
+        S::Error: std::fmt::Debug,
+    {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap();
+        (parts.headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn csp(headers: &HeaderMap) -> &str {
+        headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn secures_the_pages() {
+        let service = SecurityHeadersLayer::new(trusted()).layer(router());
+        let (headers, body) = get_headers(&service, "/").await;
+
+        assert_eq!(body, PAGE);
+        let hash = InlineHashes::default().script(HYDRATION).scripts.remove(0);
+        assert!(csp(&headers).contains(&format!("script-src 'self' 'wasm-unsafe-eval' {hash}")));
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::REFERRER_POLICY], DEFAULT_REFERRER_POLICY);
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            DEFAULT_STRICT_TRANSPORT_SECURITY
+        );
+        assert_eq!(headers[CROSS_ORIGIN_OPENER_POLICY], "same-origin");
+        assert_eq!(headers[CROSS_ORIGIN_RESOURCE_POLICY], "same-origin");
+        assert!(!headers.contains_key(CROSS_ORIGIN_EMBEDDER_POLICY));
+    }
+
+    #[tokio::test]
+    async fn blocks_the_scripts_injected_into_a_page() {
+        let service = SecurityHeadersLayer::new(trusted()).layer(router());
+        let (headers, body) = get_headers(&service, "/comments").await;
+
+        assert!(body.contains(INJECTED));
+        let injected = InlineHashes::default().script(INJECTED).scripts.remove(0);
+        assert!(!csp(&headers).contains(&injected), "{}", csp(&headers));
+        assert!(!csp(&headers).contains("'unsafe-inline'"));
+        // every page gets the same policy, whatever its content
+        let (page, _) = get_headers(&service, "/").await;
+        assert_eq!(csp(&headers), csp(&page));
+    }
+
+    #[tokio::test]
+    async fn streams_the_pages() {
+        let service = SecurityHeadersLayer::new(trusted()).layer(router());
+        let request = Request::get("/stream").body(Body::empty()).unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(1), service.oneshot(request))
+            .await
+            .expect("the page is not buffered")
+            .unwrap();
+
+        assert!(response
+            .headers()
+            .contains_key(header::CONTENT_SECURITY_POLICY));
+        let mut body = response.into_body();
+        let head = poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await;
+        let head = head.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(head, "<html><head>");
+    }
+
+    #[tokio::test]
+    async fn secures_the_assets() {
+        let service = SecurityHeadersLayer::default().layer(router());
+        let (headers, _) = get_headers(&service, "/pkg/app.css").await;
+
+        let policy = ContentSecurityPolicy::default().header_value();
+        assert_eq!(headers[header::CONTENT_SECURITY_POLICY], policy);
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_headers_of_the_inner_service() {
+        let config = SecurityConfig::default()
+            .without(header::STRICT_TRANSPORT_SECURITY)
+            .referrer_policy(HeaderValue::from_static("no-referrer"));
+        let service = SecurityHeadersLayer::new(config).layer(router());
+        let (headers, _) = get_headers(&service, "/framed").await;
+
+        assert_eq!(csp(&headers), "frame-ancestors 'self'");
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[tokio::test]
+    async fn cached_pages_keep_their_policy() {
+        let service = SecurityHeadersLayer::new(trusted()).layer(router());
+        let config = CacheConfig::default().pages(PageConfig::default());
+        let service =
+            ResponseCacheLayer::with_config(ResponseCache::default(), config).layer(service);
+
+        let (rendered, _) = get_headers(&service, "/").await;
+        let (cached, body) = get_headers(&service, "/").await;
+        assert_eq!(cached["x-cache-status"], "HIT");
+        assert_eq!(body, PAGE);
+        assert_eq!(csp(&cached), csp(&rendered));
+        let (asset, _) = get_headers(&service, "/pkg/app.css").await;
+        assert_eq!(asset[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+}
@@ -0,0 +1,14 @@
+//! Security headers for the responses of the app.
+//!
+//! A [`SecurityHeadersLayer`] adds the headers of a [`SecurityConfig`], like the
+//! `Content-Security-Policy` built with a [`ContentSecurityPolicy`], to the responses
+//! missing them.
+
+mod csp;
+mod layer;
+
+pub use csp::{ContentSecurityPolicy, InlineHashes};
+pub use layer::{
+    SecurityConfig, SecurityHeadersLayer, SecurityHeadersService, DEFAULT_REFERRER_POLICY,
+    DEFAULT_STRICT_TRANSPORT_SECURITY,
+};
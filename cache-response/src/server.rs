@@ -0,0 +1,173 @@
+//! The app served by `main`, built here so the tests serve the same one.
+
+use crate::app::{route_policies, shell, App};
+use crate::assets::AssetManifest;
+use crate::cache::watch::SiteWatcher;
+use crate::cache::{
+    warm, AdminConfig, CacheConfig, PageConfig, ResponseCache, ResponseCacheLayer,
+    ResponseCacheService, DEFAULT_METRICS_PATH,
+};
+use crate::security::{
+    ContentSecurityPolicy, InlineHashes, SecurityConfig, SecurityHeadersLayer,
+    SecurityHeadersService,
+};
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::{Router, ServiceExt};
+use leptos::config::{Env, LeptosOptions};
+use leptos::logging as console;
+use leptos::prelude::*;
+use leptos_axum::{generate_route_list, LeptosRoutes};
+use std::io;
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+use tower::Layer;
+
+/// Default number of responses the cache holds.
+pub const DEFAULT_CACHE_ENTRIES: usize = 200;
+
+/// What the server is built with besides the [`LeptosOptions`].
+#[derive(Clone, Debug)]
+pub struct ServerSettings {
+    /// Persists the cache so a restarted server comes up warm.
+    pub cache_dir: Option<PathBuf>,
+    /// The admin endpoints and the metrics are only served when a secret is provided.
+    pub admin_secret: Option<HeaderValue>,
+    /// Loads the files of `site-pkg-dir` into the cache at startup.
+    pub prewarm: bool,
+    pub max_entries: usize,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            cache_dir: None,
+            admin_secret: None,
+            prewarm: true,
+            max_entries: DEFAULT_CACHE_ENTRIES,
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Reads `CACHE_DIR`, `CACHE_ADMIN_SECRET` and `CACHE_PREWARM=false`.
+    pub fn from_env() -> Self {
+        Self {
+            cache_dir: std::env::var_os("CACHE_DIR").map(PathBuf::from),
+            admin_secret: std::env::var("CACHE_ADMIN_SECRET")
+                .ok()
+                .and_then(|secret| HeaderValue::from_str(&secret).ok()),
+            prewarm: std::env::var("CACHE_PREWARM").map_or(true, |prewarm| prewarm != "false"),
+            ..Self::default()
+        }
+    }
+}
+
+/// The routes of the app behind the security headers and the response cache.
+pub type AppService = ResponseCacheService<SecurityHeadersService<Router>>;
+
+/// The app, ready to be served.
+pub struct Server {
+    pub service: AppService,
+    pub cache: ResponseCache,
+    /// Evicts the files `cargo leptos watch` rebuilds, in development only.
+    _watcher: Option<SiteWatcher>,
+}
+
+impl Server {
+    /// Builds the app : fingerprints the assets in production, opens and warms the cache, then wraps
+    /// the leptos routes with the security headers and the cache.
+    pub async fn build(
+        leptos_options: LeptosOptions,
+        settings: ServerSettings,
+    ) -> io::Result<Self> {
+        let routes = generate_route_list(App);
+        let site_root = &*leptos_options.site_root;
+        let site_pkg_dir = &*leptos_options.site_pkg_dir;
+        // `cargo leptos watch` rebuilds the files under URLs served as immutable for a
+        // year, the assets are only fingerprinted once built for good
+        let assets = match leptos_options.env {
+            Env::DEV => AssetManifest::default(),
+            Env::PROD => AssetManifest::from_dir(site_root, site_pkg_dir)
+                .await
+                .unwrap_or_else(|error| {
+                    console::warn!("failed to fingerprint {site_root}/{site_pkg_dir}: {error}");
+                    AssetManifest::default()
+                }),
+        };
+        let cache = ResponseCache::builder().max_entries(settings.max_entries);
+        let cache = match &settings.cache_dir {
+            Some(dir) => cache.open(dir).await?,
+            None => cache.build(),
+        };
+        let mut cache_config = CacheConfig::default()
+            .precompressed_root(site_root)
+            .fingerprints(assets.clone())
+            .pages(PageConfig::default())
+            .routes(route_policies().check(routes.iter().map(|route| route.path())))
+            .prewarm(settings.prewarm);
+        // the metrics are served on the public listener, behind the admin secret
+        if let Some(secret) = settings.admin_secret {
+            cache_config = cache_config
+                .admin(AdminConfig::new(secret))
+                .metrics(DEFAULT_METRICS_PATH);
+        }
+        if let Err(error) = warm::warm_dir(&cache, &cache_config, site_root, site_pkg_dir).await {
+            console::warn!("failed to warm the cache from {site_root}/{site_pkg_dir}: {error}");
+        }
+        // `cargo leptos watch` rebuilds the site while the server keeps running
+        let watcher = match leptos_options.env {
+            Env::DEV => SiteWatcher::start(cache.clone(), &cache_config, site_root)
+                .inspect_err(|error| console::warn!("failed to watch {site_root}: {error}"))
+                .ok(),
+            Env::PROD => None,
+        };
+        // only the inline scripts leptos renders are allowed, by a hash computed here
+        // rather than from the pages, which stays valid once cached
+        let csp = ContentSecurityPolicy::default()
+            .auto_reload(&leptos_options)
+            .inline(&InlineHashes::leptos(&leptos_options));
+        let security_config = SecurityConfig::default().content_security_policy(csp);
+
+        // build our application with a route
+        let app = Router::new()
+            .leptos_routes_with_context(
+                &leptos_options,
+                routes,
+                {
+                    // the server functions invalidate the cached pages through the cache
+                    let assets = assets.clone();
+                    let cache = cache.clone();
+                    move || {
+                        provide_context(assets.clone());
+                        provide_context(cache.clone());
+                    }
+                },
+                {
+                    let leptos_options = leptos_options.clone();
+                    move || shell(leptos_options.clone())
+                },
+            )
+            .fallback(leptos_axum::file_and_error_handler_with_context(
+                move || provide_context(assets.clone()),
+                shell,
+            ))
+            .with_state(leptos_options);
+        // inside the cache, so the security headers are stored with the responses
+        let app = SecurityHeadersLayer::new(security_config).layer(app);
+        // wrap the whole router, not each route, so the admin warm-up can reach any of them
+        let service = ResponseCacheLayer::with_config(cache.clone(), cache_config).layer(app);
+
+        Ok(Self {
+            service,
+            cache,
+            _watcher: watcher,
+        })
+    }
+
+    /// Serves the app until the process exits.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let service = ServiceExt::<Request>::into_make_service(self.service.clone());
+        axum::serve(listener, service).await
+    }
+}
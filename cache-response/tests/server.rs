@@ -0,0 +1,464 @@
+//! Serves the app of `main.rs` from a temporary `site-root`, without a browser.
+
+#![cfg(feature = "ssr")]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cache_response::assets::AssetManifest;
+use cache_response::cache::{DEFAULT_ADMIN_HEADER, DEFAULT_METRICS_PATH};
+use cache_response::server::{Server, ServerSettings};
+use leptos::config::{Env, LeptosOptions};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt;
+
+const CSS: &str = "body { color: rebeccapurple; }";
+const JS: &str = "export function hydrate() {}";
+const WASM: &[u8] = b"\0asm\x01\0\0\0";
+/// Larger than the default `stream_size`, so it is streamed while being stored.
+const LARGE_WASM_SIZE: usize = 300 * 1024;
+
+/// A `site-root` as built by `cargo leptos`, removed when dropped.
+struct Site {
+    root: PathBuf,
+}
+
+impl Site {
+    fn new() -> Self {
+        static SITES: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "cache-response-site-{}-{}",
+            std::process::id(),
+            SITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let pkg = root.join("pkg");
+        std::fs::create_dir_all(&pkg).unwrap();
+        std::fs::write(pkg.join("cache-response.css"), CSS).unwrap();
+        std::fs::write(pkg.join("cache-response.js"), JS).unwrap();
+        std::fs::write(pkg.join("cache-response.wasm"), WASM).unwrap();
+        std::fs::write(pkg.join("large.wasm"), vec![b'w'; LARGE_WASM_SIZE]).unwrap();
+        std::fs::write(root.join("favicon.ico"), [0, 0, 1, 0]).unwrap();
+        Self { root }
+    }
+
+    fn options(&self, env: Env) -> LeptosOptions {
+        LeptosOptions::builder()
+            .output_name("cache-response")
+            .site_root(self.root.to_str().unwrap())
+            .site_pkg_dir("pkg")
+            .env(env)
+            .build()
+    }
+
+    async fn serve(&self, settings: ServerSettings) -> Server {
+        Server::build(self.options(Env::PROD), settings)
+            .await
+            .unwrap()
+    }
+
+    /// A cold cache, so the first request of each asset is a miss.
+    async fn serve_cold(&self) -> Server {
+        let settings = ServerSettings {
+            prewarm: false,
+            ..ServerSettings::default()
+        };
+        self.serve(settings).await
+    }
+
+    fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the cache is persisted, next to the site.
+    fn cache_dir(&self) -> PathBuf {
+        self.root.with_extension("cache")
+    }
+}
+
+impl Drop for Site {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.root);
+        _ = std::fs::remove_dir_all(self.cache_dir());
+    }
+}
+
+async fn send(server: &Server, request: Request<Body>) -> Response<Body> {
+    server.service.clone().oneshot(request).await.unwrap()
+}
+
+async fn get(server: &Server, uri: &str) -> Response<Body> {
+    send(server, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn body(response: Response<Body>) -> String {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+async fn get_br(server: &Server, uri: &str) -> Response<Body> {
+    let request = Request::get(uri)
+        .header(header::ACCEPT_ENCODING, "br")
+        .body(Body::empty())
+        .unwrap();
+    send(server, request).await
+}
+
+/// Sends a `GET` over a real connection, returning the head and the body length.
+async fn get_over_tcp(address: std::net::SocketAddr, uri: &str) -> (String, usize) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!("GET {uri} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = String::from_utf8_lossy(&response[..end]).to_lowercase();
+    (head, response.len() - end - 4)
+}
+
+fn status(headers: &HeaderMap) -> &str {
+    headers["x-cache-status"].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn caches_the_assets() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    let miss = get(&server, "/pkg/cache-response.css").await;
+    assert_eq!(miss.status(), StatusCode::OK);
+    assert_eq!(status(miss.headers()), "MISS");
+    assert_eq!(body(miss).await, CSS);
+
+    let hit = get(&server, "/pkg/cache-response.css").await;
+    assert_eq!(hit.status(), StatusCode::OK);
+    assert_eq!(status(hit.headers()), "HIT");
+    assert_eq!(hit.headers()[header::CACHE_CONTROL], "public, max-age=300");
+    assert!(hit.headers().contains_key(header::ETAG));
+    assert!(hit.headers().contains_key(header::AGE));
+    assert_eq!(body(hit).await, CSS);
+}
+
+#[tokio::test]
+async fn serves_the_new_build_compressed_after_a_restart() {
+    let site = Site::new();
+    let theme = site.path().join("pkg/theme.css");
+    let build = |color: &str| format!("p {{ color: {color}; }}\n").repeat(200);
+    std::fs::write(&theme, build("red")).unwrap();
+    let settings = ServerSettings {
+        cache_dir: Some(site.cache_dir()),
+        ..ServerSettings::default()
+    };
+
+    let server = site.serve(settings.clone()).await;
+    let response = get_br(&server, "/pkg/theme.css").await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+    drop(response);
+    // the index is written once the cache is dropped
+    drop(server);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    std::fs::write(&theme, build("blue")).unwrap();
+    let server = site.serve(settings).await;
+    let response = get_br(&server, "/pkg/theme.css").await;
+    assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+    let compressed = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let mut css = Vec::new();
+    brotli::BrotliDecompress(&mut &compressed[..], &mut css).unwrap();
+    assert_eq!(String::from_utf8(css).unwrap(), build("blue"));
+}
+
+#[tokio::test]
+async fn caches_the_streamed_assets_over_a_connection() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+
+    let (head, length) = get_over_tcp(address, "/pkg/large.wasm").await;
+    assert!(head.contains("x-cache-status: miss"), "{head}");
+    assert_eq!(length, LARGE_WASM_SIZE);
+
+    let (head, length) = get_over_tcp(address, "/pkg/large.wasm").await;
+    assert!(head.contains("x-cache-status: hit"), "{head}");
+    assert_eq!(length, LARGE_WASM_SIZE);
+}
+
+#[tokio::test]
+async fn serves_the_hashed_urls_as_immutable() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+    let manifest = AssetManifest::from_dir(site.path(), "pkg").await.unwrap();
+    let hashed = manifest.hashed("/pkg/cache-response.css").unwrap();
+
+    let response = get(&server, hashed).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(body(response).await, CSS);
+    assert_eq!(status(get(&server, hashed).await.headers()), "HIT");
+}
+
+#[tokio::test]
+async fn leaves_the_assets_unhashed_in_development() {
+    let site = Site::new();
+    let settings = ServerSettings {
+        prewarm: false,
+        ..ServerSettings::default()
+    };
+    let server = Server::build(site.options(Env::DEV), settings)
+        .await
+        .unwrap();
+
+    // a rebuild must not be served under the hash of the previous build
+    let page = body(get(&server, "/").await).await;
+    assert!(page.contains(r#"href="/pkg/cache-response.css""#), "{page}");
+    let css = get(&server, "/pkg/cache-response.css").await;
+    assert_eq!(css.headers()[header::CACHE_CONTROL], "public, max-age=300");
+}
+
+#[tokio::test]
+async fn answers_head_from_the_get_entry() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+    let head = || {
+        Request::head("/pkg/cache-response.js")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // nothing to answer from yet
+    let bypass = send(&server, head()).await;
+    assert_eq!(status(bypass.headers()), "BYPASS");
+
+    let get = get(&server, "/pkg/cache-response.js").await;
+    let length = get.headers()[header::CONTENT_LENGTH].clone();
+    assert_eq!(body(get).await, JS);
+
+    let hit = send(&server, head()).await;
+    assert_eq!(hit.status(), StatusCode::OK);
+    assert_eq!(status(hit.headers()), "HIT");
+    assert_eq!(hit.headers()[header::CONTENT_LENGTH], length);
+    assert_eq!(body(hit).await, "");
+}
+
+#[tokio::test]
+async fn revalidates_with_the_etag() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+    let response = get(&server, "/pkg/cache-response.wasm").await;
+    let etag = response.headers()[header::ETAG].clone();
+
+    let request = Request::get("/pkg/cache-response.wasm")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&server, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(body(response).await, "");
+}
+
+#[tokio::test]
+async fn evicts_the_least_recently_used_entries() {
+    let site = Site::new();
+    let settings = ServerSettings {
+        prewarm: false,
+        max_entries: 2,
+        ..ServerSettings::default()
+    };
+    let server = site.serve(settings).await;
+
+    for uri in [
+        "/pkg/cache-response.css",
+        "/pkg/cache-response.js",
+        "/pkg/cache-response.wasm",
+    ] {
+        assert_eq!(status(get(&server, uri).await.headers()), "MISS");
+    }
+    assert_eq!(server.cache.stats().await.evictions, 1);
+    let evicted = get(&server, "/pkg/cache-response.css").await;
+    assert_eq!(status(evicted.headers()), "MISS");
+    let kept = get(&server, "/pkg/cache-response.wasm").await;
+    assert_eq!(status(kept.headers()), "HIT");
+}
+
+#[tokio::test]
+async fn prewarms_the_site_package() {
+    let site = Site::new();
+    let server = site.serve(ServerSettings::default()).await;
+
+    let response = get(&server, "/pkg/cache-response.js").await;
+    assert_eq!(status(response.headers()), "HIT");
+    assert_eq!(body(response).await, JS);
+}
+
+#[tokio::test]
+async fn renders_the_missing_files_as_not_found() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    for _ in 0..2 {
+        let response = get(&server, "/pkg/missing.css").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(status(response.headers()), "BYPASS");
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let body = body(response).await;
+        assert!(body.contains("<h2>404 Not Found</h2>"), "{body}");
+        assert!(body.contains("Not Found</p>"), "{body}");
+    }
+}
+
+#[tokio::test]
+async fn caches_the_pages_without_a_session() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    let miss = get(&server, "/").await;
+    assert_eq!(miss.status(), StatusCode::OK);
+    assert_eq!(status(miss.headers()), "MISS");
+    let page = body(miss).await;
+    assert!(page.contains("Cache response !"), "{page}");
+
+    let hit = get(&server, "/").await;
+    assert_eq!(status(hit.headers()), "HIT");
+    assert_eq!(body(hit).await, page);
+
+    let request = Request::get("/")
+        .header(header::COOKIE, "id=42")
+        .body(Body::empty())
+        .unwrap();
+    let session = send(&server, request).await;
+    assert_eq!(session.status(), StatusCode::OK);
+    assert_eq!(status(session.headers()), "BYPASS");
+}
+
+#[tokio::test]
+async fn bypasses_the_cache_on_request() {
+    let site = Site::new();
+    let server = site.serve(ServerSettings::default()).await;
+
+    let request = Request::get("/pkg/cache-response.css")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&server, request).await;
+    assert_eq!(status(response.headers()), "BYPASS");
+    assert_eq!(body(response).await, CSS);
+}
+
+/// The content of the `<script>` elements without a `src`.
+fn inline_scripts(html: &str) -> Vec<&str> {
+    html.split("<script")
+        .skip(1)
+        .filter_map(|script| {
+            let (attributes, rest) = script.split_once('>')?;
+            let (content, _) = rest.split_once("</script>")?;
+            (!attributes.contains("src=")).then_some(content)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn adds_the_security_headers() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    let page = get(&server, "/").await;
+    let policy = page.headers()[header::CONTENT_SECURITY_POLICY]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert_eq!(page.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    // the scripts leptos inlined are allowed, by hashes known before the page
+    let html = body(page).await;
+    let scripts = inline_scripts(&html);
+    assert!(!scripts.is_empty(), "{html}");
+    for script in scripts {
+        let hash = STANDARD.encode(Sha256::digest(script));
+        assert!(policy.contains(&format!("'sha256-{hash}'")), "{script}");
+    }
+
+    // stored with the page
+    let hit = get(&server, "/").await;
+    assert_eq!(status(hit.headers()), "HIT");
+    assert_eq!(hit.headers()[header::CONTENT_SECURITY_POLICY], policy);
+
+    let asset = get(&server, "/pkg/cache-response.css").await;
+    assert_eq!(asset.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert!(asset.headers().contains_key(header::REFERRER_POLICY));
+}
+
+#[tokio::test]
+async fn exports_the_metrics_behind_the_admin_secret() {
+    let site = Site::new();
+    let settings = ServerSettings {
+        prewarm: false,
+        admin_secret: Some(HeaderValue::from_static("s3cret")),
+        ..ServerSettings::default()
+    };
+    let server = site.serve(settings).await;
+    for _ in 0..3 {
+        get(&server, "/pkg/cache-response.css").await;
+    }
+
+    let response = get(&server, DEFAULT_METRICS_PATH).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = Request::get(DEFAULT_METRICS_PATH)
+        .header(DEFAULT_ADMIN_HEADER, "s3cret")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&server, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let metrics = body(response).await;
+    assert!(
+        metrics.contains(r#"response_cache_requests_total{status="hit"} 2"#),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains(r#"response_cache_requests_total{status="miss"} 1"#),
+        "{metrics}"
+    );
+}
+
+#[tokio::test]
+async fn keeps_the_metrics_private_without_a_secret() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    // the metrics path is left to the router
+    let response = get(&server, DEFAULT_METRICS_PATH).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn ignores_other_methods() {
+    let site = Site::new();
+    let server = site.serve_cold().await;
+
+    let request = Request::builder()
+        .method(Method::DELETE)
+        .uri("/pkg/cache-response.css")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&server, request).await;
+    assert_eq!(status(response.headers()), "BYPASS");
+    assert_eq!(
+        status(get(&server, "/pkg/cache-response.css").await.headers()),
+        "MISS"
+    );
+}
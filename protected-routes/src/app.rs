@@ -38,7 +38,16 @@ fn HomePage() -> impl IntoView {
     let new_session = ServerAction::<NewSession>::new();
     let get_secret = ServerAction::<GetSecret>::new();
 
-    let secret = move || get_secret.value();
+    // the variant of the error survives the trip from the server
+    let secret = move || match get_secret.value().get() {
+        Some(Ok(secret)) => secret,
+        Some(Err(ServerFnError::WrappedServerError(AppError::Unauthorized(_)))) => {
+            "log in first".to_string()
+        }
+        Some(Err(ServerFnError::WrappedServerError(error))) => error.message(),
+        Some(Err(error)) => error.to_string(),
+        None => String::new(),
+    };
 
     view! {
         <h1>"Hi, hit the button « Log in » to create a new session !"</h1>
@@ -1,6 +1,8 @@
+use crate::errors::AppError;
 use crate::surreal::Token;
 use axum::body::Body;
-use http::{Request, Response, StatusCode};
+use http::{Request, Response};
+use leptos::server_fn::{response::Res, ServerFnError};
 use std::{
     future::Future,
     pin::Pin,
@@ -45,6 +47,7 @@ where
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
         let extensions = req.extensions().clone();
+        let path = req.uri().path().to_owned();
         let next_req = self.inner.call(req);
 
         let session_fut = async move {
@@ -56,10 +59,14 @@ where
                 }
             }
 
-            let response = Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
-                .body(Body::from("Session does not exist"))
-                .expect("couldn't build a response");
+            // answered like an error of the server function, so the client gets the variant
+            let error = AppError::Unauthorized("Session does not exist".to_string()).logged();
+            let status = error.status_code();
+            let mut response = <Response<Body> as Res<AppError>>::error_response(
+                &path,
+                &ServerFnError::from(error),
+            );
+            *response.status_mut() = status;
 
             Ok(response)
         };
@@ -1,7 +1,7 @@
 use crate::errors::*;
 use crate::utils::config::*;
 use axum::{async_trait, extract::FromRequestParts, Extension};
-use http::{request::Parts, StatusCode};
+use http::request::Parts;
 use leptos::logging;
 use serde::{Deserialize, Serialize};
 use std::sync::LazyLock as Lazy;
@@ -88,13 +88,18 @@ impl<S> FromRequestParts<S> for DatabaseState
 where
     S: Sync + Send,
 {
-    type Rejection = (http::StatusCode, &'static str);
+    type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        parts.extensions.get::<DatabaseState>().cloned().ok_or((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Can't extract database. Is `DatabaseStateLayer` enabled?",
-        ))
+        parts
+            .extensions
+            .get::<DatabaseState>()
+            .cloned()
+            .ok_or_else(|| {
+                AppError::Internal(
+                    "Can't extract database. Is `DatabaseStateLayer` enabled?".to_string(),
+                )
+            })
     }
 }
 
@@ -5,9 +5,14 @@ use std::env::var;
 
 type EnvResult = AppResult<String>;
 
-pub(crate) const SURREAL_NS: fn() -> EnvResult = || var("SURREAL_NS").map_err(AppError::from);
-pub(crate) const SURREAL_DB: fn() -> EnvResult = || var("SURREAL_DB").map_err(AppError::from);
-pub(crate) const SURREAL_BIND: fn() -> EnvResult = || var("SURREAL_BIND").map_err(AppError::from);
-pub(crate) const SURREAL_USER: fn() -> EnvResult = || var("SURREAL_USER").map_err(AppError::from);
-pub(crate) const SURREAL_PASS: fn() -> EnvResult = || var("SURREAL_PASS").map_err(AppError::from);
+pub(crate) const SURREAL_NS: fn() -> EnvResult = || env("SURREAL_NS");
+pub(crate) const SURREAL_DB: fn() -> EnvResult = || env("SURREAL_DB");
+pub(crate) const SURREAL_BIND: fn() -> EnvResult = || env("SURREAL_BIND");
+pub(crate) const SURREAL_USER: fn() -> EnvResult = || env("SURREAL_USER");
+pub(crate) const SURREAL_PASS: fn() -> EnvResult = || env("SURREAL_PASS");
 pub(crate) const SESSION_TOKEN_KEY: &str = "token";
+
+/// Reads a variable, naming it in the error of a missing one.
+fn env(key: &str) -> EnvResult {
+    var(key).map_err(|error| AppError::Internal(format!("{key}: {error}")).logged())
+}
@@ -1,33 +1,38 @@
+use crate::errors::AppError;
 use leptos::prelude::*;
 
-type Result<T> = std::result::Result<T, ServerFnError>;
-
 #[server(endpoint = "exist")]
-pub async fn exist_session() -> Result<bool> {
+pub async fn exist_session() -> Result<bool, ServerFnError<AppError>> {
     use crate::surreal::Token;
     use crate::utils::config::SESSION_TOKEN_KEY;
     use leptos_axum::extract;
     use tower_sessions::Session;
 
-    let session = extract::<Session>().await?;
+    let session = extract::<Session>().await.map_err(AppError::from)?;
 
-    let token = session.get::<Token>(SESSION_TOKEN_KEY).await?;
+    let token = session
+        .get::<Token>(SESSION_TOKEN_KEY)
+        .await
+        .map_err(AppError::from)?;
 
     Ok(Option::is_some(&token)) // also check if token is valide
 }
 
 #[server(endpoint = "login")]
-pub async fn new_session() -> Result<()> {
+pub async fn new_session() -> Result<(), ServerFnError<AppError>> {
     use crate::surreal::DatabaseState;
     use crate::utils::config::SESSION_TOKEN_KEY;
     use leptos_axum::extract;
     use tower_sessions::Session;
 
-    let db = extract::<DatabaseState>().await?;
-    let session = extract::<Session>().await?;
+    let db = extract::<DatabaseState>().await.map_err(AppError::from)?;
+    let session = extract::<Session>().await.map_err(AppError::from)?;
 
     let token = db.login().await?;
-    session.insert(SESSION_TOKEN_KEY, token).await?;
+    session
+        .insert(SESSION_TOKEN_KEY, token)
+        .await
+        .map_err(AppError::from)?;
     db.as_root_server().await?;
 
     leptos_axum::redirect("/dashboard");
@@ -35,13 +40,13 @@ pub async fn new_session() -> Result<()> {
 }
 
 #[server(endpoint = "logout")]
-pub async fn delete_session() -> Result<()> {
+pub async fn delete_session() -> Result<(), ServerFnError<AppError>> {
     use crate::surreal::DatabaseState;
     use leptos_axum::extract;
     use tower_sessions::Session;
 
-    let db = extract::<DatabaseState>().await?;
-    let session = extract::<Session>().await?;
+    let db = extract::<DatabaseState>().await.map_err(AppError::from)?;
+    let session = extract::<Session>().await.map_err(AppError::from)?;
 
     db.logout().await?;
     session.clear().await; // or session.delete().await?; to also delete the token in database
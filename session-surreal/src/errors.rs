@@ -1,31 +1,110 @@
 use http::status::StatusCode;
 use leptos::prelude::*;
 use std::env::VarError;
+use std::str::FromStr;
 #[cfg(feature = "ssr")]
 use std::sync::PoisonError;
 #[cfg(feature = "ssr")]
 use surrealdb::Error as SurrealError;
 use thiserror::Error;
 
-#[derive(Clone, Debug, Error)]
+/// The errors of the app.
+///
+/// The detail of an error, like the text of a surreal error, is meant for the logs : it
+/// is logged on the server where the error is raised, and its `Debug` shows it. The
+/// `Display` is only the [`code`](AppError::code) and the [`message`](AppError::message),
+/// which never exposes the detail of a server fault.
+///
+/// Server functions return a `ServerFnError<AppError>`, the error goes through its
+/// `Display` and comes back with [`FromStr`], so the hydrated client gets the same
+/// variant, without the detail. Outside of server functions, the error is answered as
+/// a JSON problem document by its `IntoResponse`.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
 pub enum AppError {
-    #[error("Not Found")]
     NotFound,
-    #[error("Env missing")]
-    EnvMissing(String),
-    #[error("Database failure")]
+    Unauthorized(String),
+    Forbidden(String),
+    Validation { field: String, message: String },
+    Conflict(String),
     Database(String),
-    #[error("Something wrong")]
-    Poison(String),
+    Internal(String),
+    Unavailable(String),
 }
 
 impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             AppError::NotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
+
+    /// A stable identifier of the variant, for the clients to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Validation { .. } => "validation",
+            AppError::Conflict(_) => "conflict",
+            AppError::Database(_) => "database",
+            AppError::Internal(_) => "internal",
+            AppError::Unavailable(_) => "unavailable",
+        }
+    }
+
+    /// What the user is told. The client errors explain what to change, the server
+    /// faults keep their detail for the logs.
+    pub fn message(&self) -> String {
+        match self {
+            AppError::NotFound => "This page does not exist.".to_string(),
+            AppError::Unauthorized(_) => "Please log in first.".to_string(),
+            AppError::Forbidden(_) => "You are not allowed to do that.".to_string(),
+            AppError::Validation { field, message } => format!("Invalid {field}: {message}"),
+            AppError::Conflict(message) => message.clone(),
+            AppError::Database(_) | AppError::Internal(_) => {
+                "Something went wrong on our side, please try again later.".to_string()
+            }
+            AppError::Unavailable(_) => {
+                "The service is unavailable for now, please try again later.".to_string()
+            }
+        }
+    }
+
+    /// Whether the server is at fault, rather than the request.
+    pub fn is_server_error(&self) -> bool {
+        self.status_code().is_server_error()
+    }
+
+    /// Logs the detail, as an error when the server is at fault.
+    #[cfg(feature = "ssr")]
+    pub fn log(&self) {
+        use leptos::logging;
+
+        match self.is_server_error() {
+            true => logging::error!("[{}] {self:?}", self.code()),
+            false => logging::debug_warn!("[{}] {self:?}", self.code()),
+        }
+    }
+
+    /// Logs the detail on the server, for an error raised with one.
+    pub fn logged(self) -> Self {
+        #[cfg(feature = "ssr")]
+        self.log();
+        self
+    }
+}
+
+/// What is sent to the client : `database: Something went wrong...`.
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
 }
 
 pub type AppResult<T> = std::result::Result<T, AppError>;
@@ -47,21 +126,25 @@ pub fn ErrorTemplate(
     // Get Errors from Signal
     let errors = errors.get_untracked();
 
-    // Downcast lets us take a type that implements `std::error::Error`
+    // Downcast lets us take a type that implements `std::error::Error`,
+    // the errors of other types are faults we didn't plan for
     let errors: Vec<AppError> = errors
         .into_iter()
-        .filter_map(|(_k, v)| v.downcast_ref::<AppError>().cloned())
+        .map(|(_k, v)| match v.downcast_ref::<AppError>() {
+            Some(error) => error.clone(),
+            None => AppError::Internal(v.to_string()).logged(),
+        })
         .collect();
-    println!("Errors: {errors:#?}");
 
     // Only the response code for the first error is actually sent from the server
     // this may be customized by the specific application
     #[cfg(feature = "ssr")]
     {
         use leptos_axum::ResponseOptions;
+
         let response = use_context::<ResponseOptions>();
-        if let Some(response) = response {
-            response.set_status(errors[0].status_code());
+        if let (Some(response), Some(error)) = (response, errors.first()) {
+            response.set_status(error.status_code());
         }
     }
 
@@ -73,34 +156,252 @@ pub fn ErrorTemplate(
             // a unique key for each item as a reference
             key=|(index, _error)| *index
             // renders each item to a view
-            children=move |error| {
-                let error_string = error.1.to_string();
-                let error_code = error.1.status_code();
+            children=move |(_index, error)| {
                 view! {
-                    <h2>{error_code.to_string()}</h2>
-                    <p>"Error: " {error_string}</p>
+                    <section class="error" data-code=error.code()>
+                        <h2>{error.status_code().to_string()}</h2>
+                        <p>{error.message()}</p>
+                    </section>
                 }
             }
         />
     }
 }
 
+/// Parses the `Display` of an error, for the client to get back the error of a server
+/// function. The detail never left the server, the variants come back without it.
+impl FromStr for AppError {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, message) = s.split_once(": ").ok_or(())?;
+        let error = match code {
+            "not_found" => AppError::NotFound,
+            "unauthorized" => AppError::Unauthorized(String::new()),
+            "forbidden" => AppError::Forbidden(String::new()),
+            "validation" => {
+                let (field, message) = message
+                    .strip_prefix("Invalid ")
+                    .and_then(|message| message.split_once(": "))
+                    .ok_or(())?;
+                AppError::Validation {
+                    field: field.to_string(),
+                    message: message.to_string(),
+                }
+            }
+            "conflict" => AppError::Conflict(message.to_string()),
+            "database" => AppError::Database(String::new()),
+            "internal" => AppError::Internal(String::new()),
+            "unavailable" => AppError::Unavailable(String::new()),
+            _ => return Err(()),
+        };
+        Ok(error)
+    }
+}
+
+/// The errors of the leptos integration, like a missing extractor or, on the client, a
+/// server that can't be reached.
+impl From<ServerFnError> for AppError {
+    fn from(error: ServerFnError) -> Self {
+        match error {
+            ServerFnError::Request(_) => Self::Unavailable(error.to_string()),
+            error => Self::Internal(error.to_string()),
+        }
+        .logged()
+    }
+}
+
+/// Answers with a [problem document](https://www.rfc-editor.org/rfc/rfc9457), whose
+/// `detail` is the user-safe message.
+#[cfg(feature = "ssr")]
+impl axum::response::IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        use http::header::CONTENT_TYPE;
+
+        self.log();
+        let status = self.status_code();
+        let problem = serde_json::json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "code": self.code(),
+            "detail": self.message(),
+        });
+        (
+            status,
+            [(CONTENT_TYPE, "application/problem+json")],
+            problem.to_string(),
+        )
+            .into_response()
+    }
+}
+
 impl From<VarError> for AppError {
     fn from(error: VarError) -> Self {
-        Self::EnvMissing(error.to_string())
+        Self::Internal(error.to_string()).logged()
     }
 }
 
 #[cfg(feature = "ssr")]
 impl From<SurrealError> for AppError {
     fn from(error: SurrealError) -> Self {
-        Self::Database(error.to_string())
+        use surrealdb::error::{Api, Db};
+
+        match &error {
+            SurrealError::Api(Api::ConnectionUninitialised | Api::Ws(_) | Api::Http(_)) => {
+                Self::Unavailable(error.to_string())
+            }
+            SurrealError::Db(Db::InvalidAuth | Db::ExpiredSession) => {
+                Self::Unauthorized(error.to_string())
+            }
+            _ => Self::Database(error.to_string()),
+        }
+        .logged()
     }
 }
 
 #[cfg(feature = "ssr")]
 impl<T> From<PoisonError<T>> for AppError {
     fn from(error: PoisonError<T>) -> Self {
-        Self::Poison(error.to_string())
+        Self::Internal(error.to_string()).logged()
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl From<tower_sessions::session::Error> for AppError {
+    fn from(error: tower_sessions::session::Error) -> Self {
+        Self::Internal(error.to_string()).logged()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_the_server_faults_to_5xx() {
+        let faults = [
+            (
+                AppError::Database("connection reset".to_string()),
+                "connection reset",
+            ),
+            (
+                AppError::Internal("poisoned lock".to_string()),
+                "poisoned lock",
+            ),
+            (AppError::Unavailable("ws closed".to_string()), "ws closed"),
+        ];
+        for (error, detail) in faults {
+            assert!(error.is_server_error(), "{error}");
+            // the detail is logged, never shown
+            assert!(!error.message().contains(detail), "{detail}");
+            assert!(!error.to_string().contains(detail), "{detail}");
+        }
+        assert_eq!(
+            AppError::from(VarError::NotPresent).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn explains_the_client_errors() {
+        let validation = AppError::Validation {
+            field: "username".to_string(),
+            message: "must not be empty".to_string(),
+        };
+        assert_eq!(validation.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(validation.code(), "validation");
+        assert_eq!(validation.message(), "Invalid username: must not be empty");
+
+        let conflict = AppError::Conflict("A session already exists.".to_string());
+        assert_eq!(conflict.status_code(), StatusCode::CONFLICT);
+        assert_eq!(conflict.message(), "A session already exists.");
+
+        assert_eq!(
+            AppError::Unauthorized("no token".to_string()).status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            AppError::Forbidden("not an admin".to_string()).code(),
+            "forbidden"
+        );
+        assert_eq!(AppError::NotFound.status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn round_trips_through_server_functions() {
+        use leptos::server_fn::error::ServerFnErrorSerde;
+
+        let errors = [
+            (AppError::NotFound, AppError::NotFound),
+            (
+                AppError::Unauthorized("no token".to_string()),
+                AppError::Unauthorized(String::new()),
+            ),
+            (
+                AppError::Forbidden("not an admin".to_string()),
+                AppError::Forbidden(String::new()),
+            ),
+            (
+                AppError::Validation {
+                    field: "username".to_string(),
+                    message: "must be: short".to_string(),
+                },
+                AppError::Validation {
+                    field: "username".to_string(),
+                    message: "must be: short".to_string(),
+                },
+            ),
+            (
+                AppError::Conflict("A session already exists.".to_string()),
+                AppError::Conflict("A session already exists.".to_string()),
+            ),
+            (
+                AppError::Database("There was an error processing a remote WS request".to_string()),
+                AppError::Database(String::new()),
+            ),
+            (
+                AppError::Internal("poisoned lock".to_string()),
+                AppError::Internal(String::new()),
+            ),
+            (
+                AppError::Unavailable("Connection uninitialised".to_string()),
+                AppError::Unavailable(String::new()),
+            ),
+        ];
+        for (error, received) in errors {
+            let error = ServerFnError::WrappedServerError(error);
+            let received = ServerFnError::WrappedServerError(received);
+            assert_eq!(ServerFnError::de(&error.ser().unwrap()), received);
+        }
+        assert_eq!("Unknown: error".parse::<AppError>(), Err(()));
+    }
+
+    #[test]
+    fn keeps_the_detail_on_the_server() {
+        use leptos::server_fn::error::ServerFnErrorSerde;
+
+        let error =
+            ServerFnError::WrappedServerError(AppError::Database("connection reset".to_string()));
+        let serialized = error.ser().unwrap();
+        assert!(!serialized.contains("connection reset"), "{serialized}");
+        assert!(
+            serialized
+                .ends_with("database: Something went wrong on our side, please try again later."),
+            "{serialized}"
+        );
+    }
+
+    #[cfg(feature = "ssr")]
+    #[test]
+    fn answers_a_problem_document() {
+        use axum::response::IntoResponse;
+
+        let response = AppError::Unavailable("ws closed".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "application/problem+json"
+        );
     }
 }